@@ -0,0 +1,143 @@
+// Exposes the window operations Nicotine needs as org.nicotine.Shell on the
+// session bus. Mutter's window APIs are only reachable from inside the shell.
+
+import Gio from 'gi://Gio';
+import Meta from 'gi://Meta';
+import * as Main from 'resource:///org/gnome/shell/ui/main.js';
+import {Extension} from 'resource:///org/gnome/shell/extensions/extension.js';
+
+const BUS_NAME = 'org.nicotine.Shell';
+const OBJECT_PATH = '/org/nicotine/Shell';
+
+const INTERFACE = `
+<node>
+  <interface name="org.nicotine.Shell">
+    <method name="ListWindows">
+      <arg type="a(ts)" direction="out" name="windows"/>
+    </method>
+    <method name="ActiveWindow">
+      <arg type="t" direction="out" name="id"/>
+    </method>
+    <method name="Activate">
+      <arg type="t" direction="in" name="id"/>
+    </method>
+    <method name="Minimize">
+      <arg type="t" direction="in" name="id"/>
+    </method>
+    <method name="Unminimize">
+      <arg type="t" direction="in" name="id"/>
+    </method>
+    <method name="FrameRect">
+      <arg type="t" direction="in" name="id"/>
+      <arg type="i" direction="out" name="x"/>
+      <arg type="i" direction="out" name="y"/>
+      <arg type="i" direction="out" name="width"/>
+      <arg type="i" direction="out" name="height"/>
+    </method>
+    <method name="MoveResize">
+      <arg type="t" direction="in" name="id"/>
+      <arg type="i" direction="in" name="x"/>
+      <arg type="i" direction="in" name="y"/>
+      <arg type="i" direction="in" name="width"/>
+      <arg type="i" direction="in" name="height"/>
+    </method>
+    <signal name="WindowsChanged"/>
+  </interface>
+</node>`;
+
+// In the order the windows were created; stacking order would change on every
+// activation and reshuffle Nicotine's cycle order
+function normalWindows() {
+    return global.display.list_all_windows()
+        .filter(w => w.get_window_type() === Meta.WindowType.NORMAL)
+        .sort((a, b) => a.get_stable_sequence() - b.get_stable_sequence());
+}
+
+function findWindow(id) {
+    const window = normalWindows().find(w => w.get_id() === id);
+    if (!window)
+        throw new Error(`No window with id ${id}`);
+    return window;
+}
+
+class ShellService {
+    ListWindows() {
+        return normalWindows().map(w => [w.get_id(), w.get_title() ?? '']);
+    }
+
+    ActiveWindow() {
+        const window = global.display.focus_window;
+        if (!window)
+            throw new Error('No active window');
+        return window.get_id();
+    }
+
+    Activate(id) {
+        Main.activateWindow(findWindow(id));
+    }
+
+    Minimize(id) {
+        findWindow(id).minimize();
+    }
+
+    Unminimize(id) {
+        findWindow(id).unminimize();
+    }
+
+    FrameRect(id) {
+        const rect = findWindow(id).get_frame_rect();
+        return [rect.x, rect.y, rect.width, rect.height];
+    }
+
+    MoveResize(id, x, y, width, height) {
+        const window = findWindow(id);
+        if (window.get_maximized())
+            window.unmaximize(Meta.MaximizeFlags.BOTH);
+        window.move_resize_frame(true, x, y, width, height);
+    }
+}
+
+export default class NicotineExtension extends Extension {
+    enable() {
+        this._dbus = Gio.DBusExportedObject.wrapJSObject(INTERFACE, new ShellService());
+        this._dbus.export(Gio.DBus.session, OBJECT_PATH);
+        this._nameId = Gio.bus_own_name(Gio.BusType.SESSION, BUS_NAME,
+            Gio.BusNameOwnerFlags.NONE, null, null, null);
+
+        // Tell Nicotine whenever the window list, focus or a title changes
+        this._windowSignals = new Map();
+        this._displaySignals = [
+            global.display.connect('window-created', (_display, window) => {
+                this._trackWindow(window);
+                this._changed();
+            }),
+            global.display.connect('notify::focus-window', () => this._changed()),
+        ];
+        normalWindows().forEach(window => this._trackWindow(window));
+    }
+
+    disable() {
+        this._displaySignals.forEach(id => global.display.disconnect(id));
+        this._windowSignals.forEach((ids, window) => ids.forEach(id => window.disconnect(id)));
+        this._displaySignals = null;
+        this._windowSignals = null;
+
+        Gio.bus_unown_name(this._nameId);
+        this._dbus.unexport();
+        this._dbus = null;
+    }
+
+    _trackWindow(window) {
+        this._windowSignals.set(window, [
+            window.connect('notify::title', () => this._changed()),
+            window.connect('unmanaged', () => {
+                this._windowSignals.delete(window);
+                this._changed();
+            }),
+        ]);
+    }
+
+    _changed() {
+        this._dbus.emit_signal('WindowsChanged', null);
+    }
+}
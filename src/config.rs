@@ -1,7 +1,9 @@
+use crate::window_manager::Backend;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
@@ -26,6 +28,181 @@ pub struct Config {
     pub mouse_device_path: Option<String>,
     #[serde(default = "default_minimize_inactive")]
     pub minimize_inactive: bool,
+    /// Force a window manager backend instead of detecting one (needs a restart)
+    #[serde(default = "default_backend")]
+    pub backend: Backend,
+    #[serde(default = "default_overlay")]
+    pub overlay: OverlayConfig,
+    #[serde(default = "default_preview")]
+    pub preview: PreviewConfig,
+}
+
+/// Screen edge or corner the overlay is anchored to
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverlayAnchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+/// `[overlay]`: placement of the overlay when it's a layer-shell surface
+/// (Wayland compositors with wlr-layer-shell). Elsewhere `overlay_x` and
+/// `overlay_y` are used.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OverlayConfig {
+    #[serde(default = "default_overlay_anchor")]
+    pub anchor: OverlayAnchor,
+    /// Gap in pixels between the overlay and the anchored edges
+    #[serde(default = "default_overlay_margin")]
+    pub margin: i32,
+    #[serde(default = "default_overlay_layout")]
+    pub layout: OverlayLayout,
+    #[serde(default = "default_overlay_theme")]
+    pub theme: OverlayTheme,
+}
+
+/// How the overlay arranges the clients
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverlayLayout {
+    /// A panel with one client per row
+    Vertical,
+    /// A single strip of client chips, sized to fit them
+    Horizontal,
+}
+
+/// Built-in overlay looks
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemePreset {
+    /// Red header on cream, the original look and size
+    #[default]
+    Classic,
+    Dark,
+    /// Dark, narrower, smaller text and no header
+    CompactDark,
+}
+
+/// `[overlay.theme]`: the overlay's look. `preset` provides every value; each
+/// other key overrides one of them.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct OverlayTheme {
+    #[serde(default)]
+    pub preset: ThemePreset,
+    /// Colors are #rrggbb
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub border: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Active client and the restack button
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accent: Option<String>,
+    /// Background opacity, 0.0 (transparent) to 1.0 (opaque)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub show_header: Option<bool>,
+    /// Extra gap between client rows, in pixels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub row_spacing: Option<f32>,
+}
+
+impl OverlayTheme {
+    fn validate(&self) -> Result<()> {
+        let colors = [
+            ("background", &self.background),
+            ("border", &self.border),
+            ("header", &self.header),
+            ("header_text", &self.header_text),
+            ("text", &self.text),
+            ("accent", &self.accent),
+        ];
+        for (key, color) in colors {
+            if let Some(color) = color {
+                if parse_color(color).is_none() {
+                    anyhow::bail!(
+                        "overlay.theme.{} ({:?}) must be a #rrggbb color",
+                        key,
+                        color
+                    );
+                }
+            }
+        }
+        if let Some(opacity) = self.opacity {
+            if !(0.0..=1.0).contains(&opacity) {
+                anyhow::bail!(
+                    "overlay.theme.opacity ({}) must be between 0.0 and 1.0",
+                    opacity
+                );
+            }
+        }
+        if self.font_size.is_some_and(|size| size <= 0.0) {
+            anyhow::bail!("overlay.theme.font_size must be greater than 0");
+        }
+        if self.width.is_some_and(|width| width <= 0.0) {
+            anyhow::bail!("overlay.theme.width must be greater than 0");
+        }
+        if self.row_spacing.is_some_and(|spacing| spacing < 0.0) {
+            anyhow::bail!("overlay.theme.row_spacing can't be negative");
+        }
+        Ok(())
+    }
+}
+
+/// `[preview]`: live client thumbnails (X11 only). Thumbnails are laid out in
+/// a row from `x`,`y` unless `positions` places a character elsewhere.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PreviewConfig {
+    #[serde(default = "default_preview_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_preview_width")]
+    pub width: u16,
+    #[serde(default = "default_preview_height")]
+    pub height: u16,
+    #[serde(default = "default_preview_x")]
+    pub x: i16,
+    #[serde(default = "default_preview_y")]
+    pub y: i16,
+    /// Gap in pixels between thumbnails in the row
+    #[serde(default = "default_preview_spacing")]
+    pub spacing: u16,
+    /// 0.0 (invisible) to 1.0 (opaque); needs a compositing window manager
+    #[serde(default = "default_preview_opacity")]
+    pub opacity: f32,
+    /// Border drawn around the active client's thumbnail, 0 for none
+    #[serde(default = "default_preview_border_width")]
+    pub border_width: u16,
+    #[serde(default = "default_preview_border_color")]
+    pub border_color: String,
+    /// Character name -> [x, y] of its thumbnail
+    #[serde(default = "default_preview_positions")]
+    pub positions: BTreeMap<String, [i16; 2]>,
+}
+
+/// Parse a `#rrggbb` color
+pub fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
 }
 
 fn default_enable_mouse() -> bool {
@@ -56,8 +233,92 @@ fn default_minimize_inactive() -> bool {
     false
 }
 
+fn default_backend() -> Backend {
+    Backend::Auto
+}
+
+fn default_overlay_anchor() -> OverlayAnchor {
+    OverlayAnchor::TopLeft
+}
+
+fn default_overlay_margin() -> i32 {
+    10
+}
+
+fn default_preview_enabled() -> bool {
+    false
+}
+
+fn default_preview_width() -> u16 {
+    256
+}
+
+fn default_preview_height() -> u16 {
+    144
+}
+
+fn default_preview_x() -> i16 {
+    10
+}
+
+fn default_preview_y() -> i16 {
+    400
+}
+
+fn default_preview_spacing() -> u16 {
+    8
+}
+
+fn default_preview_opacity() -> f32 {
+    0.9
+}
+
+fn default_preview_border_width() -> u16 {
+    2
+}
+
+fn default_preview_border_color() -> String {
+    "#c41e3a".to_string()
+}
+
+fn default_preview_positions() -> BTreeMap<String, [i16; 2]> {
+    BTreeMap::new()
+}
+
+fn default_preview() -> PreviewConfig {
+    PreviewConfig {
+        enabled: default_preview_enabled(),
+        width: default_preview_width(),
+        height: default_preview_height(),
+        x: default_preview_x(),
+        y: default_preview_y(),
+        spacing: default_preview_spacing(),
+        opacity: default_preview_opacity(),
+        border_width: default_preview_border_width(),
+        border_color: default_preview_border_color(),
+        positions: default_preview_positions(),
+    }
+}
+
+fn default_overlay_layout() -> OverlayLayout {
+    OverlayLayout::Vertical
+}
+
+fn default_overlay_theme() -> OverlayTheme {
+    OverlayTheme::default()
+}
+
+fn default_overlay() -> OverlayConfig {
+    OverlayConfig {
+        anchor: default_overlay_anchor(),
+        margin: default_overlay_margin(),
+        layout: default_overlay_layout(),
+        theme: default_overlay_theme(),
+    }
+}
+
 impl Config {
-    fn config_dir() -> PathBuf {
+    pub fn config_dir() -> PathBuf {
         let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
         path.push("nicotine");
         path
@@ -69,24 +330,180 @@ impl Config {
         path
     }
 
-    /// Load character order from characters.txt
-    /// Each line is a character name (without "EVE - " prefix)
-    /// Returns None if file doesn't exist
-    pub fn load_characters() -> Option<Vec<String>> {
+    fn characters_path() -> PathBuf {
         let mut path = Self::config_dir();
         path.push("characters.txt");
+        path
+    }
+
+    /// Load character order from characters.txt
+    /// Each line is a character name (without "EVE - " prefix)
+    /// Returns Ok(None) if the file doesn't exist, and an error if a name is
+    /// listed twice (the second slot could never be reached)
+    pub fn load_characters() -> Result<Option<Vec<String>>> {
+        Self::load_characters_from(&Self::characters_path())
+    }
+
+    fn load_characters_from(path: &Path) -> Result<Option<Vec<String>>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+            }
+        };
+
+        let characters = Self::parse_characters(&contents);
+        Self::validate_characters(&characters)?;
+        Ok(Some(characters))
+    }
+
+    fn parse_characters(contents: &str) -> Vec<String> {
+        contents
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect()
+    }
+
+    /// Move `character` to `slot` (1-based) in characters.txt, shifting the
+    /// characters after it down. Without a characters.txt one is created from
+    /// `clients`, the current window order. Returns the new order.
+    pub fn assign_slot(character: &str, slot: usize, clients: &[String]) -> Result<Vec<String>> {
+        let path = Self::characters_path();
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                clients.iter().map(|name| format!("{}\n", name)).collect()
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+            }
+        };
+
+        let contents = Self::move_to_slot(&contents, character, slot);
+        fs::create_dir_all(Self::config_dir())?;
+        fs::write(&path, &contents)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(Self::parse_characters(&contents))
+    }
+
+    /// Rewrite characters.txt contents with `character` at `slot`. Comments and
+    /// blank lines stay where they are; only the name lines are reordered.
+    fn move_to_slot(contents: &str, character: &str, slot: usize) -> String {
+        let mut names = Self::parse_characters(contents);
+        names.retain(|name| name != character);
+        let index = slot.saturating_sub(1).min(names.len());
+        names.insert(index, character.to_string());
+
+        let mut names = names.into_iter();
+        let mut output = String::new();
+        for line in contents.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                output.push_str(line);
+            } else if let Some(name) = names.next() {
+                output.push_str(&name);
+            } else {
+                continue;
+            }
+            output.push('\n');
+        }
+        // A character that wasn't listed before adds a line
+        for name in names {
+            output.push_str(&name);
+            output.push('\n');
+        }
+        output
+    }
 
-        if !path.exists() {
-            return None;
+    /// Re-read config.toml for a live reload. Unlike `load`, a missing file
+    /// is an error rather than a reason to write a new default.
+    pub fn reload() -> Result<Self> {
+        Self::reload_from(&Self::config_path())
+    }
+
+    fn reload_from(config_path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(config_path)
+            .with_context(|| format!("Failed to read {}", config_path.display()))?;
+        Self::parse(&contents)
+    }
+
+    /// Parse config.toml and reject values that `validate` wouldn't accept
+    fn parse(contents: &str) -> Result<Self> {
+        let config: Self = toml::from_str(contents).context("Failed to parse config.toml")?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate_characters(characters: &[String]) -> Result<()> {
+        for (i, name) in characters.iter().enumerate() {
+            if let Some(first) = characters[..i].iter().position(|n| n == name) {
+                anyhow::bail!(
+                    "characters.txt lists '{}' twice (slots {} and {})",
+                    name,
+                    first + 1,
+                    i + 1
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Check values that would otherwise break stacking or mouse handling
+    pub fn validate(&self) -> Result<()> {
+        if self.display_width == 0 || self.display_height == 0 {
+            anyhow::bail!("display_width and display_height must be greater than 0");
+        }
+        if self.eve_width == 0 || self.eve_width > self.display_width {
+            anyhow::bail!(
+                "eve_width ({}) must be between 1 and display_width ({})",
+                self.eve_width,
+                self.display_width
+            );
+        }
+        if self.panel_height >= self.display_height {
+            anyhow::bail!(
+                "panel_height ({}) must be less than display_height ({})",
+                self.panel_height,
+                self.display_height
+            );
+        }
+        if self.overlay.margin < 0 {
+            anyhow::bail!("overlay.margin ({}) can't be negative", self.overlay.margin);
         }
+        self.overlay.theme.validate()?;
+        if self.preview.width == 0 || self.preview.height == 0 {
+            anyhow::bail!("preview.width and preview.height must be greater than 0");
+        }
+        if !(0.0..=1.0).contains(&self.preview.opacity) {
+            anyhow::bail!(
+                "preview.opacity ({}) must be between 0.0 and 1.0",
+                self.preview.opacity
+            );
+        }
+        if parse_color(&self.preview.border_color).is_none() {
+            anyhow::bail!(
+                "preview.border_color ({:?}) must be a #rrggbb color",
+                self.preview.border_color
+            );
+        }
+        if self.enable_mouse_buttons && self.forward_button == self.backward_button {
+            anyhow::bail!(
+                "forward_button and backward_button must differ (both are {})",
+                self.forward_button
+            );
+        }
+        Ok(())
+    }
 
-        fs::read_to_string(&path).ok().map(|contents| {
-            contents
-                .lines()
-                .map(|line| line.trim().to_string())
-                .filter(|line| !line.is_empty() && !line.starts_with('#'))
-                .collect()
-        })
+    /// Whether the mouse listener needs restarting to pick up `other`
+    pub fn mouse_settings_changed(&self, other: &Config) -> bool {
+        self.enable_mouse_buttons != other.enable_mouse_buttons
+            || self.forward_button != other.forward_button
+            || self.backward_button != other.backward_button
+            || self.mouse_device_name != other.mouse_device_name
+            || self.mouse_device_path != other.mouse_device_path
     }
 
     fn detect_display_size() -> (u32, u32) {
@@ -116,11 +533,13 @@ impl Config {
     }
 
     pub fn load() -> Result<Self> {
-        let config_path = Self::config_path();
+        Self::load_from(&Self::config_path())
+    }
 
+    fn load_from(config_path: &Path) -> Result<Self> {
         // Try to load existing config
-        if let Ok(contents) = fs::read_to_string(&config_path) {
-            return toml::from_str(&contents).context("Failed to parse config.toml");
+        if let Ok(contents) = fs::read_to_string(config_path) {
+            return Self::parse(&contents);
         }
 
         // Auto-generate config based on detected display
@@ -143,6 +562,9 @@ impl Config {
             mouse_device_name: None,
             mouse_device_path: None,
             minimize_inactive: false,
+            backend: Backend::Auto,
+            overlay: default_overlay(),
+            preview: default_preview(),
         };
 
         // Save the generated config
@@ -150,7 +572,7 @@ impl Config {
             fs::create_dir_all(parent)?;
         }
         let contents = toml::to_string_pretty(&config)?;
-        fs::write(&config_path, contents)?;
+        fs::write(config_path, contents)?;
         println!("Created config: {}", config_path.display());
         println!("Edit it to customize window sizes and positions");
 
@@ -176,6 +598,9 @@ impl Config {
             mouse_device_name: None,
             mouse_device_path: None,
             minimize_inactive: false,
+            backend: Backend::Auto,
+            overlay: default_overlay(),
+            preview: default_preview(),
         };
 
         if let Some(parent) = config_path.parent() {
@@ -195,6 +620,7 @@ impl Config {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::test_config;
 
     #[test]
     fn test_eve_height_adjusted_with_panel() {
@@ -213,6 +639,9 @@ mod tests {
             mouse_device_name: None,
             mouse_device_path: None,
             minimize_inactive: false,
+            backend: Backend::Auto,
+            overlay: default_overlay(),
+            preview: default_preview(),
         };
 
         // Height should be: 1080 - 40 = 1040
@@ -236,11 +665,210 @@ mod tests {
             mouse_device_name: None,
             mouse_device_path: None,
             minimize_inactive: false,
+            backend: Backend::Auto,
+            overlay: default_overlay(),
+            preview: default_preview(),
         };
 
         assert_eq!(config.eve_height_adjusted(), 1080);
     }
 
+    #[test]
+    fn test_validate_accepts_defaults() {
+        assert!(test_config().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_rejects_oversized_eve_width() {
+        let config = Config {
+            eve_width: 2000,
+            ..test_config()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_panel_taller_than_display() {
+        let config = Config {
+            panel_height: 1080,
+            ..test_config()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_identical_mouse_buttons() {
+        let config = Config {
+            backward_button: 276,
+            ..test_config()
+        };
+        assert!(config.validate().is_err());
+
+        // Irrelevant when mouse buttons are off
+        let config = Config {
+            enable_mouse_buttons: false,
+            ..config
+        };
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_mouse_settings_changed() {
+        let config = test_config();
+        assert!(!config.mouse_settings_changed(&Config {
+            minimize_inactive: true,
+            ..test_config()
+        }));
+        assert!(config.mouse_settings_changed(&Config {
+            forward_button: 277,
+            ..test_config()
+        }));
+        assert!(config.mouse_settings_changed(&Config {
+            mouse_device_path: Some("/dev/input/event3".to_string()),
+            ..test_config()
+        }));
+    }
+
+    #[test]
+    fn test_parse_characters_skips_comments_and_blanks() {
+        let characters = Config::parse_characters("# Main\nAlpha\n\n  Beta  \n#Gamma\n");
+        assert_eq!(characters, vec!["Alpha".to_string(), "Beta".to_string()]);
+    }
+
+    #[test]
+    fn test_move_to_slot_keeps_comments() {
+        let contents = "# Main\nAlpha\n\n# Alts\nBeta\nGamma\n";
+        assert_eq!(
+            Config::move_to_slot(contents, "Gamma", 1),
+            "# Main\nGamma\n\n# Alts\nAlpha\nBeta\n"
+        );
+        // Unlisted characters are added, past-the-end slots clamp to the last one
+        assert_eq!(Config::move_to_slot("Alpha\n", "Beta", 5), "Alpha\nBeta\n");
+        assert_eq!(
+            Config::move_to_slot("Alpha\nBeta\n", "Beta", 0),
+            "Beta\nAlpha\n"
+        );
+    }
+
+    #[test]
+    fn test_load_validates_like_reload() {
+        let dir = std::env::temp_dir().join(format!("nicotine-test-load-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // Wider than the display: stacking would underflow
+        let config_path = dir.join("config.toml");
+        let mut table = toml::Table::try_from(test_config()).unwrap();
+        table.insert("eve_width".into(), 2000.into());
+        fs::write(&config_path, toml::to_string(&table).unwrap()).unwrap();
+        assert!(Config::reload_from(&config_path).is_err());
+        assert!(Config::load_from(&config_path).is_err());
+
+        let characters_path = dir.join("characters.txt");
+        fs::write(&characters_path, "Alpha\nBeta\nAlpha\n").unwrap();
+        assert!(Config::load_characters_from(&characters_path).is_err());
+        assert_eq!(
+            Config::load_characters_from(&dir.join("missing.txt")).unwrap(),
+            None
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_validate_characters_rejects_duplicates() {
+        let characters = vec!["Alpha".to_string(), "Beta".to_string(), "Alpha".to_string()];
+        let err = Config::validate_characters(&characters).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "characters.txt lists 'Alpha' twice (slots 1 and 3)"
+        );
+    }
+
+    #[test]
+    fn test_backend_defaults_to_auto() {
+        let mut table = toml::Table::try_from(test_config()).unwrap();
+        table.remove("backend");
+        let config: Config = table.try_into().unwrap();
+        assert_eq!(config.backend, Backend::Auto);
+
+        table = toml::Table::try_from(test_config()).unwrap();
+        table.insert("backend".into(), "hyprland".into());
+        let config: Config = table.try_into().unwrap();
+        assert_eq!(config.backend, Backend::Hyprland);
+    }
+
+    #[test]
+    fn test_overlay_section() {
+        let config: Config = toml::from_str(
+            "display_width = 1920\ndisplay_height = 1080\npanel_height = 0\n\
+             eve_width = 1037\neve_height = 1080\noverlay_x = 10.0\noverlay_y = 10.0\n\
+             [overlay]\nanchor = \"bottom-right\"\nlayout = \"horizontal\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.overlay.anchor, OverlayAnchor::BottomRight);
+        assert_eq!(config.overlay.margin, 10);
+        assert_eq!(config.overlay.layout, OverlayLayout::Horizontal);
+
+        // Written configs keep the table after the plain keys
+        let written = toml::to_string_pretty(&test_config()).unwrap();
+        assert!(written
+            .contains("[overlay]\nanchor = \"top-left\"\nmargin = 10\nlayout = \"vertical\""));
+    }
+
+    #[test]
+    fn test_overlay_theme_section() {
+        let config: Config = toml::from_str(
+            "display_width = 1920\ndisplay_height = 1080\npanel_height = 0\n\
+             eve_width = 1037\neve_height = 1080\noverlay_x = 10.0\noverlay_y = 10.0\n\
+             [overlay.theme]\npreset = \"compact-dark\"\naccent = \"#50a0ff\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.overlay.theme.preset, ThemePreset::CompactDark);
+        assert_eq!(config.overlay.theme.accent.as_deref(), Some("#50a0ff"));
+        assert_eq!(config.overlay.theme.font_size, None);
+        assert!(config.validate().is_ok());
+
+        // Unset overrides aren't written out
+        let written = toml::to_string_pretty(&test_config()).unwrap();
+        assert!(written.contains("[overlay.theme]\npreset = \"classic\"\n"));
+
+        let mut config = test_config();
+        config.overlay.theme.opacity = Some(1.5);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_preview_section() {
+        let config: Config = toml::from_str(
+            "display_width = 1920\ndisplay_height = 1080\npanel_height = 0\n\
+             eve_width = 1037\neve_height = 1080\noverlay_x = 10.0\noverlay_y = 10.0\n\
+             [preview]\nenabled = true\nwidth = 320\n\
+             [preview.positions]\nAlpha = [100, 50]\n",
+        )
+        .unwrap();
+        assert!(config.preview.enabled);
+        assert_eq!((config.preview.width, config.preview.height), (320, 144));
+        assert_eq!(config.preview.positions["Alpha"], [100, 50]);
+        assert!(config.validate().is_ok());
+
+        let config = Config {
+            preview: PreviewConfig {
+                border_color: "red".to_string(),
+                ..default_preview()
+            },
+            ..test_config()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#c41e3a"), Some([0xc4, 0x1e, 0x3a]));
+        assert_eq!(parse_color("c41e3a"), None);
+        assert_eq!(parse_color("#c41e3"), None);
+        assert_eq!(parse_color("#gg0000"), None);
+    }
+
     #[test]
     fn test_config_serialization() {
         let config = Config {
@@ -258,6 +886,9 @@ mod tests {
             mouse_device_name: None,
             mouse_device_path: None,
             minimize_inactive: false,
+            backend: Backend::Auto,
+            overlay: default_overlay(),
+            preview: default_preview(),
         };
 
         let toml_str = toml::to_string(&config).unwrap();
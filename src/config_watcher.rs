@@ -0,0 +1,95 @@
+use crate::config::Config;
+use crate::daemon::{Command, CommandSender};
+use anyhow::{Context, Result};
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use std::ffi::OsStr;
+use std::os::fd::AsFd;
+use std::time::Duration;
+
+/// Files whose changes trigger a reload
+const WATCHED_FILES: [&str; 2] = ["config.toml", "characters.txt"];
+
+/// Editors often save in several steps (truncate, write, rename); wait for them to settle
+const SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+/// Watches ~/.config/nicotine/ and asks the daemon to reload when
+/// config.toml or characters.txt change
+pub struct ConfigWatcher {
+    inotify: Inotify,
+}
+
+impl ConfigWatcher {
+    pub fn new() -> Result<Self> {
+        let dir = Config::config_dir();
+        std::fs::create_dir_all(&dir)?;
+
+        let inotify =
+            Inotify::init(InitFlags::IN_CLOEXEC).context("Failed to initialize inotify")?;
+
+        // Watch the directory rather than the files so atomic saves (write to a
+        // temp file, then rename over the original) are picked up too
+        inotify
+            .add_watch(
+                &dir,
+                AddWatchFlags::IN_CLOSE_WRITE
+                    | AddWatchFlags::IN_MOVED_TO
+                    | AddWatchFlags::IN_DELETE,
+            )
+            .with_context(|| format!("Failed to watch {}", dir.display()))?;
+
+        Ok(Self { inotify })
+    }
+
+    /// Run the watcher in a background thread
+    pub fn spawn(self, commands: CommandSender) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            if let Err(e) = self.run(commands) {
+                eprintln!("Config watcher error: {}", e);
+            }
+        })
+    }
+
+    fn run(self, commands: CommandSender) -> Result<()> {
+        loop {
+            let events = self.inotify.read_events()?;
+            if !events.iter().any(|event| is_watched(event.name.as_deref())) {
+                continue;
+            }
+
+            // Let the save finish, then discard whatever else it generated
+            std::thread::sleep(SETTLE_DELAY);
+            self.drain()?;
+
+            println!("Configuration changed, reloading");
+            commands.send(Command::Reload);
+        }
+    }
+
+    fn drain(&self) -> Result<()> {
+        let fd = self.inotify.as_fd();
+        let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+        while poll(&mut fds, PollTimeout::ZERO)? > 0 {
+            self.inotify.read_events()?;
+        }
+        Ok(())
+    }
+}
+
+fn is_watched(name: Option<&OsStr>) -> bool {
+    name.is_some_and(|name| WATCHED_FILES.iter().any(|file| name == *file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_config_files_are_watched() {
+        assert!(is_watched(Some(OsStr::new("config.toml"))));
+        assert!(is_watched(Some(OsStr::new("characters.txt"))));
+        assert!(!is_watched(Some(OsStr::new("config.toml~"))));
+        assert!(!is_watched(Some(OsStr::new(".characters.txt.swp"))));
+        assert!(!is_watched(None));
+    }
+}
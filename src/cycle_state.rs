@@ -1,13 +1,49 @@
-use crate::window_manager::{EveWindow, WindowManager};
+use crate::events::{Event, EventBus};
+use crate::window_manager::{EveWindow, WindowId, WindowManager};
 use anyhow::Result;
-use std::fs;
-use std::path::Path;
+use std::fmt;
+use std::sync::Arc;
+
+/// Reasons a targeted switch can't be resolved to a window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwitchError {
+    /// Target is beyond the number of names in characters.txt
+    SlotOutOfRange { target: usize, configured: usize },
+    /// Target is beyond the number of open windows (no characters.txt)
+    WindowOutOfRange { target: usize, windows: usize },
+    /// The character for this slot has no open client
+    CharacterNotFound(String),
+}
+
+impl fmt::Display for SwitchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwitchError::SlotOutOfRange { target, configured } => write!(
+                f,
+                "Target {} is out of range (only {} characters configured)",
+                target, configured
+            ),
+            SwitchError::WindowOutOfRange { target, windows } => write!(
+                f,
+                "Target {} is out of range (only {} windows)",
+                target, windows
+            ),
+            SwitchError::CharacterNotFound(name) => {
+                write!(f, "Character '{}' not found in active windows", name)
+            }
+        }
+    }
+}
 
-const INDEX_FILE: &str = "/tmp/nicotine-index";
+impl std::error::Error for SwitchError {}
 
 pub struct CycleState {
     current_index: usize,
     windows: Vec<EveWindow>,
+    /// characters.txt as the daemon last loaded it, so the overlay shows the
+    /// same numbers `nicotine N` switches by
+    character_order: Option<Vec<String>>,
+    events: Option<Arc<EventBus>>,
 }
 
 impl CycleState {
@@ -15,42 +51,55 @@ impl CycleState {
         Self {
             current_index: 0,
             windows: Vec::new(),
+            character_order: None,
+            events: None,
+        }
+    }
+
+    /// Create a state that publishes client and focus changes to `events`
+    pub fn with_events(events: Arc<EventBus>) -> Self {
+        Self {
+            events: Some(events),
+            ..Self::new()
         }
     }
 
     pub fn update_windows(&mut self, windows: Vec<EveWindow>) {
+        let previous_active = self.active_window_id();
+
+        // Removals go first, so a subscriber inserting each added client at
+        // its index ends up with the same list
+        if let Some(events) = &self.events {
+            for window in &self.windows {
+                if !windows.iter().any(|w| w.id == window.id) {
+                    events.publish(Event::ClientRemoved {
+                        window_id: window.id,
+                        character: window.title.clone(),
+                    });
+                }
+            }
+            for (i, window) in windows.iter().enumerate() {
+                if !self.windows.iter().any(|w| w.id == window.id) {
+                    events.publish(Event::ClientAdded {
+                        index: i + 1,
+                        window_id: window.id,
+                        character: window.title.clone(),
+                    });
+                }
+            }
+        }
+
         self.windows = windows;
         // Clamp current index
         if self.current_index >= self.windows.len() && !self.windows.is_empty() {
             self.current_index = 0;
         }
+
+        self.publish_focus_change(previous_active);
     }
 
     pub fn cycle_forward(&mut self, wm: &dyn WindowManager, minimize_inactive: bool) -> Result<()> {
-        if self.windows.is_empty() {
-            return Ok(());
-        }
-
-        let previous_index = self.current_index;
-        self.current_index = (self.current_index + 1) % self.windows.len();
-        self.write_index();
-
-        let new_window_id = self.windows[self.current_index].id;
-
-        if minimize_inactive {
-            // Restore new window first (in case it was minimized)
-            let _ = wm.restore_window(new_window_id);
-        }
-
-        wm.activate_window(new_window_id)?;
-
-        if minimize_inactive && previous_index != self.current_index {
-            // Minimize the previous window after activating the new one
-            let previous_window_id = self.windows[previous_index].id;
-            let _ = wm.minimize_window(previous_window_id);
-        }
-
-        Ok(())
+        self.cycle_by(1, wm, minimize_inactive)
     }
 
     pub fn cycle_backward(
@@ -58,48 +107,70 @@ impl CycleState {
         wm: &dyn WindowManager,
         minimize_inactive: bool,
     ) -> Result<()> {
-        if self.windows.is_empty() {
-            return Ok(());
-        }
-
-        let previous_index = self.current_index;
-        if self.current_index == 0 {
-            self.current_index = self.windows.len() - 1;
-        } else {
-            self.current_index -= 1;
-        }
-
-        self.write_index();
+        self.cycle_by(-1, wm, minimize_inactive)
+    }
 
-        let new_window_id = self.windows[self.current_index].id;
+    /// Move `steps` clients forward (positive) or backward (negative), wrapping around.
+    /// Several queued presses can be applied at once with a single activation.
+    pub fn cycle_by(
+        &mut self,
+        steps: isize,
+        wm: &dyn WindowManager,
+        minimize_inactive: bool,
+    ) -> Result<()> {
+        let activation = self.plan_cycle(steps);
+        self.carry_out(activation, wm, minimize_inactive)
+    }
 
-        if minimize_inactive {
-            // Restore new window first (in case it was minimized)
-            let _ = wm.restore_window(new_window_id);
+    /// The activation `cycle_by` would carry out, without doing it
+    pub fn plan_cycle(&self, steps: isize) -> Option<Activation> {
+        if self.windows.is_empty() {
+            return None;
         }
 
-        wm.activate_window(new_window_id)?;
+        let len = self.windows.len() as isize;
+        let target_index = (self.current_index as isize + steps).rem_euclid(len) as usize;
+        Some(Activation {
+            previous: self.windows[self.current_index].id,
+            target: self.windows[target_index].id,
+        })
+    }
 
-        if minimize_inactive && previous_index != self.current_index {
-            // Minimize the previous window after activating the new one
-            let previous_window_id = self.windows[previous_index].id;
-            let _ = wm.minimize_window(previous_window_id);
-        }
+    /// Record that `activation` went through, telling subscribers about it
+    pub fn commit(&mut self, activation: Activation) {
+        self.sync_with_active(activation.target);
+    }
 
+    fn carry_out(
+        &mut self,
+        activation: Option<Activation>,
+        wm: &dyn WindowManager,
+        minimize_inactive: bool,
+    ) -> Result<()> {
+        if let Some(activation) = activation {
+            activation.run(wm, minimize_inactive)?;
+            self.commit(activation);
+        }
         Ok(())
     }
 
-    fn write_index(&self) {
-        let _ = fs::write(INDEX_FILE, self.current_index.to_string());
+    fn active_window_id(&self) -> Option<WindowId> {
+        self.windows.get(self.current_index).map(|w| w.id)
     }
 
-    pub fn read_index_from_file() -> Option<usize> {
-        if Path::new(INDEX_FILE).exists() {
-            fs::read_to_string(INDEX_FILE)
-                .ok()
-                .and_then(|s| s.trim().parse().ok())
-        } else {
-            None
+    fn publish_focus_change(&self, previous_active: Option<WindowId>) {
+        let Some(events) = &self.events else {
+            return;
+        };
+
+        if let Some(window) = self.windows.get(self.current_index) {
+            if previous_active != Some(window.id) {
+                events.publish(Event::FocusChanged {
+                    index: self.current_index + 1,
+                    window_id: window.id,
+                    character: window.title.clone(),
+                });
+            }
         }
     }
 
@@ -107,24 +178,38 @@ impl CycleState {
         &self.windows
     }
 
+    /// The client with this window ID, if it's being tracked
+    pub fn get_window(&self, window_id: WindowId) -> Option<&EveWindow> {
+        self.windows.iter().find(|w| w.id == window_id)
+    }
+
     pub fn get_current_index(&self) -> usize {
         self.current_index
     }
 
     pub fn set_current_index(&mut self, index: usize) {
         if index < self.windows.len() || self.windows.is_empty() {
+            let previous_active = self.active_window_id();
             self.current_index = index;
+            self.publish_focus_change(previous_active);
         }
     }
 
-    pub fn sync_with_active(&mut self, active_window: u32) {
+    pub fn sync_with_active(&mut self, active_window: WindowId) {
         // Find which window is active and update current_index
-        for (i, window) in self.windows.iter().enumerate() {
-            if window.id == active_window {
-                self.current_index = i;
-                break;
-            }
+        let previous_active = self.active_window_id();
+        if let Some(i) = self.windows.iter().position(|w| w.id == active_window) {
+            self.current_index = i;
         }
+        self.publish_focus_change(previous_active);
+    }
+
+    pub fn character_order(&self) -> Option<&[String]> {
+        self.character_order.as_deref()
+    }
+
+    pub fn set_character_order(&mut self, character_order: Option<Vec<String>>) {
+        self.character_order = character_order;
     }
 
     /// Switch to a specific target number (1-indexed)
@@ -137,19 +222,29 @@ impl CycleState {
         minimize_inactive: bool,
         character_order: Option<&[String]>,
     ) -> Result<()> {
+        let activation = self.plan_switch(target, character_order)?;
+        self.carry_out(activation, wm, minimize_inactive)
+    }
+
+    /// The activation `switch_to` would carry out, without doing it
+    pub fn plan_switch(
+        &self,
+        target: usize,
+        character_order: Option<&[String]>,
+    ) -> Result<Option<Activation>> {
         if self.windows.is_empty() || target == 0 {
-            return Ok(());
+            return Ok(None);
         }
 
         let target_index = if let Some(characters) = character_order {
             // Use character order from characters.txt
             let target_idx = target - 1; // Convert to 0-indexed
             if target_idx >= characters.len() {
-                anyhow::bail!(
-                    "Target {} is out of range (only {} characters configured)",
+                return Err(SwitchError::SlotOutOfRange {
                     target,
-                    characters.len()
-                );
+                    configured: characters.len(),
+                }
+                .into());
             }
 
             let target_name = &characters[target_idx];
@@ -158,42 +253,78 @@ impl CycleState {
             self.windows
                 .iter()
                 .position(|w| w.title == *target_name)
-                .ok_or_else(|| {
-                    anyhow::anyhow!("Character '{}' not found in active windows", target_name)
-                })?
+                .ok_or_else(|| SwitchError::CharacterNotFound(target_name.clone()))?
         } else {
             // Fall back to window list order
             let target_idx = target - 1; // Convert to 0-indexed
             if target_idx >= self.windows.len() {
-                anyhow::bail!(
-                    "Target {} is out of range (only {} windows)",
+                return Err(SwitchError::WindowOutOfRange {
                     target,
-                    self.windows.len()
-                );
+                    windows: self.windows.len(),
+                }
+                .into());
             }
             target_idx
         };
 
+        Ok(self.plan_index(target_index))
+    }
+
+    /// Switch to a specific client, e.g. one clicked in the overlay
+    pub fn focus(
+        &mut self,
+        window_id: WindowId,
+        wm: &dyn WindowManager,
+        minimize_inactive: bool,
+    ) -> Result<()> {
+        let activation = self.plan_focus(window_id)?;
+        self.carry_out(activation, wm, minimize_inactive)
+    }
+
+    /// The activation `focus` would carry out, without doing it
+    pub fn plan_focus(&self, window_id: WindowId) -> Result<Option<Activation>> {
+        let index = self
+            .windows
+            .iter()
+            .position(|w| w.id == window_id)
+            .ok_or_else(|| anyhow::anyhow!("Window {} is not an EVE client", window_id))?;
+        Ok(self.plan_index(index))
+    }
+
+    fn plan_index(&self, target_index: usize) -> Option<Activation> {
         // Don't do anything if already on target
         if target_index == self.current_index {
-            return Ok(());
+            return None;
         }
 
-        let previous_index = self.current_index;
-        self.current_index = target_index;
-        self.write_index();
+        Some(Activation {
+            previous: self.windows[self.current_index].id,
+            target: self.windows[target_index].id,
+        })
+    }
+}
 
-        let new_window_id = self.windows[self.current_index].id;
+/// Bringing up one client in place of another. Worked out from the state by
+/// the `plan_*` methods and carried out without it, so the daemon doesn't
+/// hold its state lock while the window manager answers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Activation {
+    pub previous: WindowId,
+    pub target: WindowId,
+}
 
+impl Activation {
+    pub fn run(&self, wm: &dyn WindowManager, minimize_inactive: bool) -> Result<()> {
         if minimize_inactive {
-            let _ = wm.restore_window(new_window_id);
+            // Restore new window first (in case it was minimized)
+            let _ = wm.restore_window(self.target);
         }
 
-        wm.activate_window(new_window_id)?;
+        wm.activate_window(self.target)?;
 
-        if minimize_inactive {
-            let previous_window_id = self.windows[previous_index].id;
-            let _ = wm.minimize_window(previous_window_id);
+        if minimize_inactive && self.previous != self.target {
+            // Minimize the previous window after activating the new one
+            let _ = wm.minimize_window(self.previous);
         }
 
         Ok(())
@@ -204,9 +335,9 @@ impl CycleState {
 mod tests {
     use super::*;
 
-    fn create_test_window(id: u32, title: &str) -> EveWindow {
+    fn create_test_window(id: u64, title: &str) -> EveWindow {
         EveWindow {
-            id,
+            id: WindowId(id),
             title: title.to_string(),
         }
     }
@@ -269,11 +400,11 @@ mod tests {
         state.update_windows(windows);
 
         // Sync with window id 300
-        state.sync_with_active(300);
+        state.sync_with_active(WindowId(300));
         assert_eq!(state.get_current_index(), 2);
 
         // Sync with window id 100
-        state.sync_with_active(100);
+        state.sync_with_active(WindowId(100));
         assert_eq!(state.get_current_index(), 0);
     }
 
@@ -288,7 +419,7 @@ mod tests {
         state.current_index = 1;
 
         // Sync with non-existent window - index shouldn't change
-        state.sync_with_active(999);
+        state.sync_with_active(WindowId(999));
         assert_eq!(state.get_current_index(), 1);
     }
 
@@ -303,8 +434,8 @@ mod tests {
 
         let returned_windows = state.get_windows();
         assert_eq!(returned_windows.len(), 2);
-        assert_eq!(returned_windows[0].id, 1);
-        assert_eq!(returned_windows[1].id, 2);
+        assert_eq!(returned_windows[0].id, WindowId(1));
+        assert_eq!(returned_windows[1].id, WindowId(2));
     }
 
     #[test]
@@ -328,7 +459,7 @@ mod tests {
         assert_eq!(state.get_current_index(), 0);
 
         // Syncing with the only window should work
-        state.sync_with_active(1);
+        state.sync_with_active(WindowId(1));
         assert_eq!(state.get_current_index(), 0);
     }
 
@@ -362,7 +493,7 @@ mod tests {
 
     // Mock WindowManager for testing switch_to
     struct MockWindowManager {
-        activated_windows: std::sync::Mutex<Vec<u32>>,
+        activated_windows: std::sync::Mutex<Vec<WindowId>>,
     }
 
     impl MockWindowManager {
@@ -372,17 +503,25 @@ mod tests {
             }
         }
 
-        fn get_activated(&self) -> Vec<u32> {
+        fn get_activated(&self) -> Vec<WindowId> {
             self.activated_windows.lock().unwrap().clone()
         }
     }
 
     impl WindowManager for MockWindowManager {
+        fn backend(&self) -> crate::window_manager::Backend {
+            crate::window_manager::Backend::X11
+        }
+
+        fn capabilities(&self) -> crate::window_manager::Capabilities {
+            crate::window_manager::Capabilities::default()
+        }
+
         fn get_eve_windows(&self) -> anyhow::Result<Vec<EveWindow>> {
             Ok(vec![])
         }
 
-        fn activate_window(&self, window_id: u32) -> anyhow::Result<()> {
+        fn activate_window(&self, window_id: WindowId) -> anyhow::Result<()> {
             self.activated_windows.lock().unwrap().push(window_id);
             Ok(())
         }
@@ -395,19 +534,19 @@ mod tests {
             Ok(())
         }
 
-        fn get_active_window(&self) -> anyhow::Result<u32> {
-            Ok(0)
+        fn get_active_window(&self) -> anyhow::Result<WindowId> {
+            Ok(WindowId(0))
         }
 
-        fn find_window_by_title(&self, _title: &str) -> anyhow::Result<Option<u32>> {
+        fn find_window_by_title(&self, _title: &str) -> anyhow::Result<Option<WindowId>> {
             Ok(None)
         }
 
-        fn minimize_window(&self, _window_id: u32) -> anyhow::Result<()> {
+        fn minimize_window(&self, _window_id: WindowId) -> anyhow::Result<()> {
             Ok(())
         }
 
-        fn restore_window(&self, _window_id: u32) -> anyhow::Result<()> {
+        fn restore_window(&self, _window_id: WindowId) -> anyhow::Result<()> {
             Ok(())
         }
     }
@@ -427,7 +566,7 @@ mod tests {
         // Switch to target 2 (0-indexed: 1)
         state.switch_to(2, &wm, false, None).unwrap();
         assert_eq!(state.get_current_index(), 1);
-        assert_eq!(wm.get_activated(), vec![200]);
+        assert_eq!(wm.get_activated(), vec![WindowId(200)]);
     }
 
     #[test]
@@ -449,7 +588,7 @@ mod tests {
         // Switch to target 1 (Alpha) - should find window 200
         state.switch_to(1, &wm, false, Some(&char_order)).unwrap();
         assert_eq!(state.get_current_index(), 1); // Index of Alpha in windows
-        assert_eq!(wm.get_activated(), vec![200]);
+        assert_eq!(wm.get_activated(), vec![WindowId(200)]);
     }
 
     #[test]
@@ -484,7 +623,13 @@ mod tests {
 
         // Switch to target 5 when only 2 windows exist
         let result = state.switch_to(5, &wm, false, None);
-        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<SwitchError>(),
+            Some(&SwitchError::WindowOutOfRange {
+                target: 5,
+                windows: 2
+            })
+        );
     }
 
     #[test]
@@ -503,7 +648,37 @@ mod tests {
 
         // Switch to target 3 (Gamma) - not logged in
         let result = state.switch_to(3, &wm, false, Some(&char_order));
-        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<SwitchError>(),
+            Some(&SwitchError::CharacterNotFound("Gamma".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_cycle_by_wraps_in_both_directions() {
+        let mut state = CycleState::new();
+        state.update_windows(vec![
+            create_test_window(100, "Alpha"),
+            create_test_window(200, "Beta"),
+            create_test_window(300, "Gamma"),
+        ]);
+
+        let wm = MockWindowManager::new();
+
+        state.cycle_by(4, &wm, false).unwrap();
+        assert_eq!(state.get_current_index(), 1);
+
+        state.cycle_by(-2, &wm, false).unwrap();
+        assert_eq!(state.get_current_index(), 2);
+
+        state.cycle_backward(&wm, false).unwrap();
+        state.cycle_forward(&wm, false).unwrap();
+        assert_eq!(state.get_current_index(), 2);
+
+        assert_eq!(
+            wm.get_activated(),
+            vec![WindowId(200), WindowId(300), WindowId(200), WindowId(300)]
+        );
     }
 
     #[test]
@@ -519,6 +694,73 @@ mod tests {
         assert!(wm.get_activated().is_empty());
     }
 
+    #[test]
+    fn test_update_windows_publishes_added_and_removed() {
+        let events = Arc::new(EventBus::new());
+        let rx = events.subscribe();
+        let mut state = CycleState::with_events(Arc::clone(&events));
+
+        state.update_windows(vec![create_test_window(100, "Alpha")]);
+        state.update_windows(vec![create_test_window(200, "Beta")]);
+
+        let received: Vec<Event> = rx.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![
+                Event::ClientAdded {
+                    index: 1,
+                    window_id: WindowId(100),
+                    character: "Alpha".to_string()
+                },
+                Event::FocusChanged {
+                    index: 1,
+                    window_id: WindowId(100),
+                    character: "Alpha".to_string()
+                },
+                Event::ClientRemoved {
+                    window_id: WindowId(100),
+                    character: "Alpha".to_string()
+                },
+                Event::ClientAdded {
+                    index: 1,
+                    window_id: WindowId(200),
+                    character: "Beta".to_string()
+                },
+                Event::FocusChanged {
+                    index: 1,
+                    window_id: WindowId(200),
+                    character: "Beta".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_focus_change_published_once_per_switch() {
+        let events = Arc::new(EventBus::new());
+        let mut state = CycleState::with_events(Arc::clone(&events));
+        state.update_windows(vec![
+            create_test_window(100, "Alpha"),
+            create_test_window(200, "Beta"),
+        ]);
+
+        let rx = events.subscribe();
+        let wm = MockWindowManager::new();
+        state.switch_to(2, &wm, false, None).unwrap();
+        // Syncing with the window we just switched to is not a change
+        state.sync_with_active(WindowId(200));
+
+        let received: Vec<Event> = rx.try_iter().collect();
+        assert_eq!(
+            received,
+            vec![Event::FocusChanged {
+                index: 2,
+                window_id: WindowId(200),
+                character: "Beta".to_string()
+            }]
+        );
+    }
+
     #[test]
     fn test_switch_to_empty_windows_does_nothing() {
         let mut state = CycleState::new();
@@ -529,4 +771,22 @@ mod tests {
         state.switch_to(1, &wm, false, None).unwrap();
         assert!(wm.get_activated().is_empty());
     }
+
+    #[test]
+    fn test_focus_by_window_id() {
+        let mut state = CycleState::new();
+        state.update_windows(vec![
+            create_test_window(100, "Alpha"),
+            create_test_window(200, "Beta"),
+        ]);
+
+        let wm = MockWindowManager::new();
+        state.focus(WindowId(200), &wm, false).unwrap();
+        assert_eq!(state.get_current_index(), 1);
+        assert_eq!(wm.get_activated(), vec![WindowId(200)]);
+
+        // Windows that aren't clients are rejected without touching focus
+        assert!(state.focus(WindowId(300), &wm, false).is_err());
+        assert_eq!(state.get_current_index(), 1);
+    }
 }
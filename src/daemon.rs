@@ -1,23 +1,64 @@
 use crate::config::Config;
-use crate::cycle_state::CycleState;
-use crate::mouse_listener::MouseListener;
-use crate::window_manager::WindowManager;
-use anyhow::Result;
-use std::fs;
-use std::io::{BufRead, BufReader, Write};
+use crate::config_watcher::ConfigWatcher;
+use crate::cycle_state::{Activation, CycleState};
+use crate::events::{Event, EventBus, EventStream};
+use crate::ipc::{self, ErrorCode, Request, Response, StatusReport};
+use crate::mouse_listener::{MouseListener, MouseListenerHandle};
+use crate::preview::{self, PreviewHandle};
+use crate::runtime;
+use crate::window_manager::{EveWindow, WindowId, WindowManager};
+use anyhow::{Context, Result};
+use nix::sys::signal::{kill, sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
+use nix::unistd::{getuid, Pid};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::fd::{BorrowedFd, IntoRawFd};
 use std::os::unix::net::{UnixListener, UnixStream};
-use std::path::Path;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-const SOCKET_PATH: &str = "/tmp/nicotine.sock";
+/// How long a client waits for the daemon to answer
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
 
-#[derive(Debug)]
+/// How long the daemon waits on a client to send its request or read the response
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long the worker leaves connection threads to acknowledge `quit` before exiting
+const QUIT_GRACE: Duration = Duration::from_millis(500);
+
+/// How often the window list is refreshed when the backend can't report changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
 pub enum Command {
     Forward,
     Backward,
     Switch(usize),
     Refresh,
+    /// Stack every client. Runs in the daemon so subscribers get `stacked`.
+    Stack,
+    /// Re-read config.toml and characters.txt, publishing `config_reloaded`
+    /// (or `config_error`) to subscribers
+    Reload,
+    Subscribe,
+    Status,
     Quit,
+    /// Switch to a specific client, e.g. one clicked in the overlay
+    Focus(WindowId),
+    Minimize(WindowId),
+    Restore(WindowId),
+    /// Stack a single client, leaving the others where they are
+    StackWindow(WindowId),
+    /// Move a client's character to `slot` (1-based) in characters.txt
+    AssignSlot {
+        window_id: WindowId,
+        slot: usize,
+    },
 }
 
 impl Command {
@@ -27,6 +68,10 @@ impl Command {
             "forward" => Some(Command::Forward),
             "backward" => Some(Command::Backward),
             "refresh" => Some(Command::Refresh),
+            "stack" => Some(Command::Stack),
+            "reload" => Some(Command::Reload),
+            "subscribe" => Some(Command::Subscribe),
+            "status" => Some(Command::Status),
             "quit" => Some(Command::Quit),
             _ => {
                 // Check for switch:N format
@@ -41,77 +86,233 @@ impl Command {
     }
 }
 
+/// A command queued for the worker thread. `reply` is None for fire-and-forget
+/// senders such as the mouse listener.
+pub struct Job {
+    command: Command,
+    reply: Option<Sender<Response>>,
+}
+
+/// Handle for queueing commands on the daemon's worker thread
+#[derive(Clone)]
+pub struct CommandSender(Sender<Job>);
+
+impl CommandSender {
+    /// Queue a command without waiting for its result
+    pub fn send(&self, command: Command) {
+        let _ = self.0.send(Job {
+            command,
+            reply: None,
+        });
+    }
+
+    /// Queue a command and wait for the worker to carry it out
+    pub fn request(&self, command: Command) -> Response {
+        let (tx, rx) = mpsc::channel();
+        // Shutting down can take a while with many clients to restore, and the
+        // worker exits the process itself once it's done
+        let quit = command == Command::Quit;
+        let job = Job {
+            command,
+            reply: Some(tx),
+        };
+
+        if self.0.send(job).is_err() {
+            return Response::error(ErrorCode::Unavailable, "Daemon is shutting down");
+        }
+
+        let reply = if quit {
+            rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            rx.recv_timeout(RESPONSE_TIMEOUT)
+        };
+        match reply {
+            Ok(response) => response,
+            Err(RecvTimeoutError::Timeout) => Response::error(
+                ErrorCode::Unavailable,
+                "Timed out waiting for the window manager",
+            ),
+            // Jobs still queued behind a quit are dropped unanswered
+            Err(RecvTimeoutError::Disconnected) => {
+                Response::error(ErrorCode::Unavailable, "Daemon is shutting down")
+            }
+        }
+    }
+}
+
+/// Work left after merging a burst of queued jobs
+#[derive(Debug, PartialEq)]
+enum Work {
+    /// Net number of forward (positive) or backward (negative) steps
+    Cycle(isize),
+    Run(Command),
+}
+
+struct Batch {
+    work: Work,
+    replies: Vec<Sender<Response>>,
+    /// Callers whose switch was replaced by a later one in the same burst
+    superseded: Vec<Sender<Response>>,
+}
+
+/// Merge runs of cycle presses into a single net step, and runs of switches into
+/// the last target, so a burst of input costs one window activation instead of many.
+/// Everything else runs in order.
+fn coalesce(jobs: Vec<Job>) -> Vec<Batch> {
+    let mut batches: Vec<Batch> = Vec::new();
+
+    for job in jobs {
+        let work = match job.command {
+            Command::Forward => Work::Cycle(1),
+            Command::Backward => Work::Cycle(-1),
+            command => Work::Run(command),
+        };
+
+        let merged = match (batches.last_mut(), &work) {
+            (Some(last), Work::Cycle(step)) => match &mut last.work {
+                Work::Cycle(steps) => {
+                    *steps += step;
+                    Some(last)
+                }
+                _ => None,
+            },
+            (Some(last), Work::Run(Command::Switch(target))) => match &mut last.work {
+                Work::Run(Command::Switch(previous)) => {
+                    *previous = *target;
+                    // Only the last target's caller gets its outcome
+                    let replaced = std::mem::take(&mut last.replies);
+                    last.superseded.extend(replaced);
+                    Some(last)
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+
+        match merged {
+            Some(last) => last.replies.extend(job.reply),
+            None => batches.push(Batch {
+                work,
+                replies: job.reply.into_iter().collect(),
+                superseded: Vec::new(),
+            }),
+        }
+    }
+
+    batches
+}
+
 pub struct Daemon {
     wm: Arc<dyn WindowManager>,
     state: Arc<Mutex<CycleState>>,
-    config: Config,
-    character_order: Option<Vec<String>>,
+    events: Arc<EventBus>,
+    /// Shared with an overlay in the same process, which follows reloads through it
+    config: Arc<Mutex<Config>>,
+    commands: CommandSender,
+    jobs: Option<Receiver<Job>>,
+    mouse_listener: Option<MouseListenerHandle>,
+    previews: Option<PreviewHandle>,
 }
 
 impl Daemon {
-    pub fn new(wm: Arc<dyn WindowManager>, config: Config) -> Self {
-        let state = Arc::new(Mutex::new(CycleState::new()));
+    /// `character_order` is characters.txt as already loaded (and validated)
+    /// by `Config::load_characters`
+    pub fn new(
+        wm: Arc<dyn WindowManager>,
+        config: Config,
+        character_order: Option<Vec<String>>,
+    ) -> Self {
+        let events = Arc::new(EventBus::new());
+        let state = Arc::new(Mutex::new(CycleState::with_events(Arc::clone(&events))));
 
         // Initialize windows
         if let Ok(windows) = wm.get_eve_windows() {
             state.lock().unwrap().update_windows(windows);
         }
 
-        // Load character order for targeted cycling
-        let character_order = Config::load_characters();
+        // Character order for targeted cycling
         if character_order.is_some() {
             println!("Loaded character order from characters.txt");
         }
+        state.lock().unwrap().set_character_order(character_order);
+
+        // All window manager work happens on one worker thread, fed by this queue
+        let (tx, rx) = mpsc::channel();
 
         Self {
             wm,
             state,
-            config,
-            character_order,
+            events,
+            config: Arc::new(Mutex::new(config)),
+            commands: CommandSender(tx),
+            jobs: Some(rx),
+            mouse_listener: None,
+            previews: None,
         }
     }
 
-    pub fn run(&mut self) -> Result<()> {
+    /// The daemon's live cycle state, for an overlay running in the same process
+    pub fn state(&self) -> Arc<Mutex<CycleState>> {
+        Arc::clone(&self.state)
+    }
+
+    /// The daemon's live configuration, for an overlay running in the same process
+    pub fn config(&self) -> Arc<Mutex<Config>> {
+        Arc::clone(&self.config)
+    }
+
+    /// Queue for commands to the daemon's worker, for use within the same process
+    pub fn commands(&self) -> CommandSender {
+        self.commands.clone()
+    }
+
+    pub fn run(mut self) -> Result<()> {
+        runtime::ensure_runtime_dir()?;
+        let socket_path = runtime::socket_path();
+
         // Remove old socket if it exists
-        let _ = fs::remove_file(SOCKET_PATH);
+        let _ = fs::remove_file(&socket_path);
 
-        let listener = UnixListener::bind(SOCKET_PATH)?;
-        println!("EVE Multibox daemon listening on {}", SOCKET_PATH);
+        let listener = UnixListener::bind(&socket_path)?;
+        runtime::write_pid_file()?;
+        println!("EVE Multibox daemon listening on {}", socket_path.display());
 
-        // Start mouse event listener if enabled
-        if self.config.enable_mouse_buttons {
-            let mouse_listener = MouseListener::new(self.config.clone());
-            let wm_clone = Arc::clone(&self.wm);
-            let state_clone = Arc::clone(&self.state);
+        let jobs = self.jobs.take().context("Daemon is already running")?;
+        let commands = self.commands.clone();
 
-            match mouse_listener.spawn(wm_clone, state_clone) {
-                Ok(_) => println!("Mouse button listener started"),
-                Err(e) => {
-                    eprintln!("Warning: Could not start mouse listener: {}", e);
-                    eprintln!(
-                        "Mouse buttons will not work. You can disable this warning by setting"
-                    );
-                    eprintln!("'enable_mouse_buttons = false' in ~/.config/nicotine/config.toml");
-                }
+        // `nicotine stop` signals daemons that don't answer `quit`; clean up
+        // the same way for those
+        spawn_signal_listener(commands.clone())?;
+
+        self.start_mouse_listener();
+        self.start_previews();
+
+        // Reload automatically when config.toml or characters.txt change
+        match ConfigWatcher::new() {
+            Ok(watcher) => {
+                watcher.spawn(commands.clone());
+                println!("Watching {} for changes", Config::config_dir().display());
             }
+            Err(e) => eprintln!("Warning: Config hot-reload unavailable: {:#}", e),
         }
 
-        // Refresh window list periodically in background
-        let wm_clone = Arc::clone(&self.wm);
-        let state_clone = Arc::clone(&self.state);
-        std::thread::spawn(move || loop {
-            std::thread::sleep(std::time::Duration::from_millis(500));
-            if let Ok(windows) = wm_clone.get_eve_windows() {
-                state_clone.lock().unwrap().update_windows(windows);
-            }
-        });
+        self.spawn_refresh();
+
+        let events = Arc::clone(&self.events);
+        std::thread::spawn(move || self.process_jobs(jobs));
 
+        // Each connection gets its own thread so a slow or hung client never
+        // holds up anyone else
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => {
-                    if let Err(e) = self.handle_client(stream) {
-                        eprintln!("Error handling client: {}", e);
-                    }
+                    let commands = commands.clone();
+                    let events = Arc::clone(&events);
+                    std::thread::spawn(move || {
+                        if let Err(e) = handle_client(stream, &commands, &events) {
+                            eprintln!("Error handling client: {}", e);
+                        }
+                    });
                 }
                 Err(e) => {
                     eprintln!("Connection error: {}", e);
@@ -122,69 +323,613 @@ impl Daemon {
         Ok(())
     }
 
-    fn handle_client(&mut self, stream: UnixStream) -> Result<()> {
-        let mut reader = BufReader::new(&stream);
-        let mut line = String::new();
-        reader.read_line(&mut line)?;
-
-        if let Some(command) = Command::from_str(&line) {
-            match command {
-                Command::Forward => {
-                    let mut state = self.state.lock().unwrap();
+    /// Keep the window list and active client current in the background: on
+    /// every change notification when the backend provides them, otherwise by polling
+    fn spawn_refresh(&self) {
+        let (notify, changes) = mpsc::channel();
+        let mut watching = match self.wm.watch_windows(notify) {
+            Ok(watching) => watching,
+            Err(e) => {
+                eprintln!("Warning: Window change notifications unavailable: {:#}", e);
+                false
+            }
+        };
+        if watching {
+            println!("Tracking windows via window manager events");
+        }
 
-                    // Sync with active window first
-                    if let Ok(active) = self.wm.get_active_window() {
-                        state.sync_with_active(active);
-                    }
+        let wm = Arc::clone(&self.wm);
+        let state = Arc::clone(&self.state);
+        std::thread::spawn(move || loop {
+            if watching {
+                if changes.recv().is_err() {
+                    eprintln!("Window change notifications stopped, polling instead");
+                    watching = false;
+                    continue;
+                }
+                // One refresh covers a burst of changes
+                changes.try_iter().for_each(drop);
+            } else {
+                std::thread::sleep(POLL_INTERVAL);
+            }
 
-                    state.cycle_forward(&*self.wm, self.config.minimize_inactive)?;
+            if let Ok(windows) = wm.get_eve_windows() {
+                let active = wm.get_active_window();
+                let mut state = state.lock().unwrap();
+                state.update_windows(windows);
+                if let Ok(active) = active {
+                    state.sync_with_active(active);
                 }
-                Command::Backward => {
-                    let mut state = self.state.lock().unwrap();
+            }
+        });
+    }
 
-                    // Sync with active window first
-                    if let Ok(active) = self.wm.get_active_window() {
-                        state.sync_with_active(active);
-                    }
+    /// Worker loop: run queued commands one batch at a time
+    fn process_jobs(mut self, jobs: Receiver<Job>) {
+        while let Ok(job) = jobs.recv() {
+            // Pick up everything that queued while the last command ran
+            let mut pending = vec![job];
+            pending.extend(jobs.try_iter());
 
-                    state.cycle_backward(&*self.wm, self.config.minimize_inactive)?;
+            for batch in coalesce(pending) {
+                for reply in batch.superseded {
+                    let _ = reply.send(Response::error(
+                        ErrorCode::Superseded,
+                        "Superseded by a later switch",
+                    ));
                 }
-                Command::Switch(target) => {
-                    let mut state = self.state.lock().unwrap();
 
-                    // Sync with active window first
-                    if let Ok(active) = self.wm.get_active_window() {
-                        state.sync_with_active(active);
-                    }
+                let quit = batch.work == Work::Run(Command::Quit);
+                let response = match self.execute(batch.work) {
+                    Ok(response) => response,
+                    Err(e) => Response::from_error(&e),
+                };
 
-                    state.switch_to(
-                        target,
-                        &*self.wm,
-                        self.config.minimize_inactive,
-                        self.character_order.as_deref(),
-                    )?;
+                if quit {
+                    self.shutdown();
                 }
-                Command::Refresh => {
-                    let windows = self.wm.get_eve_windows()?;
-                    self.state.lock().unwrap().update_windows(windows);
+                for reply in batch.replies {
+                    let _ = reply.send(response.clone());
                 }
-                Command::Quit => {
+                if quit {
+                    // The only place the daemon exits: give connection threads
+                    // a moment to pass the acknowledgement on first
+                    std::thread::sleep(QUIT_GRACE);
                     std::process::exit(0);
                 }
             }
         }
+    }
+
+    fn execute(&mut self, work: Work) -> Result<Response> {
+        let command = match work {
+            Work::Cycle(0) => return Ok(Response::ok()),
+            Work::Cycle(steps) => {
+                self.activate(|state| Ok(state.plan_cycle(steps)))?;
+                return Ok(Response::ok());
+            }
+            Work::Run(command) => command,
+        };
+
+        match command {
+            Command::Switch(target) => {
+                self.activate(|state| state.plan_switch(target, state.character_order()))?;
+            }
+            Command::Focus(window_id) => self.activate(|state| state.plan_focus(window_id))?,
+            Command::Minimize(window_id) => self.wm.minimize_window(window_id)?,
+            Command::Restore(window_id) => self.wm.restore_window(window_id)?,
+            Command::StackWindow(window_id) => {
+                let window = self.client(window_id)?;
+                let config = self.config.lock().unwrap().clone();
+                self.wm.stack_windows(&[window], &config)?;
+                self.events.publish(Event::Stacked { count: 1 });
+            }
+            Command::AssignSlot { window_id, slot } => {
+                let window = self.client(window_id)?;
+                let clients: Vec<String> = {
+                    let state = self.state.lock().unwrap();
+                    state
+                        .get_windows()
+                        .iter()
+                        .map(|w| w.title.clone())
+                        .collect()
+                };
+                // The config watcher reloads this too; set it now so the next switch sees it
+                let character_order = Config::assign_slot(&window.title, slot, &clients)?;
+                self.state
+                    .lock()
+                    .unwrap()
+                    .set_character_order(Some(character_order));
+            }
+            Command::Refresh => {
+                let windows = self.wm.get_eve_windows()?;
+                self.state.lock().unwrap().update_windows(windows);
+            }
+            Command::Stack => {
+                let windows = self.wm.get_eve_windows()?;
+                let config = self.config.lock().unwrap().clone();
+                self.wm.stack_windows(&windows, &config)?;
+                self.events.publish(Event::Stacked {
+                    count: windows.len(),
+                });
+            }
+            Command::Reload => return Ok(self.reload()),
+            Command::Status => {
+                let state = self.state.lock().unwrap();
+                let report = StatusReport::new(&state, self.wm.backend(), self.wm.capabilities());
+                return Ok(Response::Ok { data: Some(report) });
+            }
+            // Cycling is folded into Work::Cycle and subscriptions never reach the worker
+            Command::Forward | Command::Backward | Command::Subscribe | Command::Quit => {}
+        }
 
+        Ok(Response::ok())
+    }
+
+    /// Work out a switch from the state, synced with the active window first,
+    /// then carry it out with the state unlocked so the overlay never waits on
+    /// the window manager
+    fn activate(&self, plan: impl FnOnce(&CycleState) -> Result<Option<Activation>>) -> Result<()> {
+        let active = self.wm.get_active_window();
+        let activation = {
+            let mut state = self.state.lock().unwrap();
+            if let Ok(active) = active {
+                state.sync_with_active(active);
+            }
+            plan(&state)?
+        };
+
+        if let Some(activation) = activation {
+            activation.run(&*self.wm, self.minimize_inactive())?;
+            self.state.lock().unwrap().commit(activation);
+        }
         Ok(())
     }
+
+    fn minimize_inactive(&self) -> bool {
+        self.config.lock().unwrap().minimize_inactive
+    }
+
+    /// The tracked client with this window ID
+    fn client(&self, window_id: WindowId) -> Result<EveWindow> {
+        self.state
+            .lock()
+            .unwrap()
+            .get_window(window_id)
+            .cloned()
+            .with_context(|| format!("Window {} is not an EVE client", window_id))
+    }
+
+    /// (Re)start the mouse listener with the current settings, stopping any previous one
+    fn start_mouse_listener(&mut self) {
+        if let Some(listener) = self.mouse_listener.take() {
+            listener.stop();
+        }
+
+        let config = self.config.lock().unwrap().clone();
+        if !config.enable_mouse_buttons {
+            return;
+        }
+
+        let mouse_listener = MouseListener::new(config);
+
+        match mouse_listener.spawn(self.commands.clone()) {
+            Ok(handle) => {
+                println!("Mouse button listener started");
+                self.mouse_listener = Some(handle);
+            }
+            Err(e) => {
+                eprintln!("Warning: Could not start mouse listener: {}", e);
+                eprintln!("Mouse buttons will not work. You can disable this warning by setting");
+                eprintln!("'enable_mouse_buttons = false' in ~/.config/nicotine/config.toml");
+            }
+        }
+    }
+
+    /// (Re)start the client thumbnails with the current settings, stopping any previous ones
+    fn start_previews(&mut self) {
+        if let Some(previews) = self.previews.take() {
+            previews.stop();
+        }
+
+        let config = self.config.lock().unwrap().clone();
+        if !config.preview.enabled {
+            return;
+        }
+        if !self.wm.capabilities().thumbnails {
+            eprintln!(
+                "Warning: Previews aren't supported with the {} backend",
+                self.wm.backend()
+            );
+            return;
+        }
+        if config.minimize_inactive {
+            eprintln!(
+                "Warning: minimize_inactive is on, so every client but the active one previews as black"
+            );
+        }
+
+        match preview::spawn(
+            config.preview,
+            Arc::clone(&self.state),
+            self.commands.clone(),
+        ) {
+            Ok(handle) => {
+                println!("Client previews started");
+                self.previews = Some(handle);
+            }
+            Err(e) => eprintln!("Warning: Could not start previews: {:#}", e),
+        }
+    }
+
+    /// Validate config.toml and characters.txt, then swap both in at once.
+    /// On error the running configuration is kept untouched.
+    fn reload(&mut self) -> Response {
+        let loaded = Config::reload().and_then(|config| Ok((config, Config::load_characters()?)));
+
+        let (config, character_order) = match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                let message = format!("{:#}", e);
+                eprintln!("Config reload failed: {}", message);
+                self.events.publish(Event::ConfigError {
+                    message: message.clone(),
+                });
+                return Response::error(ErrorCode::InvalidConfig, message);
+            }
+        };
+
+        let (restart_mouse, restart_previews) = {
+            let mut current = self.config.lock().unwrap();
+            let changed = (
+                current.mouse_settings_changed(&config),
+                current.preview != config.preview,
+            );
+            *current = config;
+            changed
+        };
+        self.state
+            .lock()
+            .unwrap()
+            .set_character_order(character_order);
+
+        if restart_mouse {
+            self.start_mouse_listener();
+        }
+        if restart_previews {
+            self.start_previews();
+        }
+
+        println!("Configuration reloaded");
+        self.events.publish(Event::ConfigReloaded);
+        Response::ok()
+    }
+
+    /// Leave the desktop as we found it: bring back clients hidden by
+    /// `minimize_inactive` and remove the socket and PID file
+    fn shutdown(&self) {
+        if self.minimize_inactive() {
+            let (windows, active) = {
+                let state = self.state.lock().unwrap();
+                let windows = state.get_windows().to_vec();
+                let active = windows.get(state.get_current_index()).map(|w| w.id);
+                (windows, active)
+            };
+            for window in &windows {
+                let _ = self.wm.restore_window(window.id);
+            }
+            // Restoring may raise other clients, so re-focus the active one last
+            if let Some(active) = active {
+                let _ = self.wm.activate_window(active);
+            }
+        }
+
+        let _ = fs::remove_file(runtime::socket_path());
+        let _ = fs::remove_file(runtime::pid_path());
+        println!("Daemon stopped");
+    }
 }
 
-pub fn send_command(command: &str) -> Result<()> {
-    if !Path::new(SOCKET_PATH).exists() {
-        anyhow::bail!("Daemon not running. Start with: eve-multibox daemon");
+/// Serve one connection: read a single request, hand it to the worker and write back the
+/// response. `subscribe` connections stay on this thread and stream events instead.
+fn handle_client(
+    mut stream: UnixStream,
+    commands: &CommandSender,
+    events: &EventBus,
+) -> Result<()> {
+    // The runtime dir is already private, but only ever obey our own user
+    let peer = getsockopt(&stream, PeerCredentials)?;
+    if peer.uid() != getuid().as_raw() {
+        let response = Response::error(
+            ErrorCode::PermissionDenied,
+            "Daemon belongs to another user",
+        );
+        stream.write_all(ipc::encode(&response)?.as_bytes())?;
+        anyhow::bail!("Rejected client with uid {}", peer.uid());
+    }
+
+    // Don't let a client that never finishes its line (or never reads) pin this thread
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+
+    let response = match ipc::parse_request(&line) {
+        Ok(request) if request.command == Command::Subscribe => {
+            return stream_events(stream, events);
+        }
+        // After `quit` the worker ends the process once this has been answered
+        Ok(request) => commands.request(request.command),
+        Err(response) => response,
+    };
+
+    stream.write_all(ipc::encode(&response)?.as_bytes())?;
+    Ok(())
+}
+
+/// Acknowledge a `subscribe` request, then stream events to the client until it disconnects
+fn stream_events(mut stream: UnixStream, events: &EventBus) -> Result<()> {
+    let rx = events.subscribe();
+    stream.write_all(ipc::encode(&Response::ok())?.as_bytes())?;
+
+    for event in rx {
+        if stream.write_all(ipc::encode(&event)?.as_bytes()).is_err() {
+            break; // Subscriber went away
+        }
     }
 
-    let mut stream = UnixStream::connect(SOCKET_PATH)?;
-    writeln!(stream, "{}", command)?;
-    stream.flush()?;
     Ok(())
 }
+
+/// Write end of the pipe that carries SIGTERM to `spawn_signal_listener`
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_terminate(_: nix::libc::c_int) {
+    // write(2) is async-signal-safe, unlike anything that would queue the quit directly
+    let fd = unsafe { BorrowedFd::borrow_raw(SIGNAL_PIPE.load(Ordering::Relaxed)) };
+    let _ = nix::unistd::write(fd, &[0]);
+}
+
+/// Queue `Command::Quit` on SIGTERM, so the daemon shuts down exactly as it
+/// does for a `quit` request
+fn spawn_signal_listener(commands: CommandSender) -> Result<()> {
+    let (read, write) = nix::unistd::pipe().context("Failed to create signal pipe")?;
+    SIGNAL_PIPE.store(write.into_raw_fd(), Ordering::Relaxed);
+
+    // SA_RESTART keeps the signal from interrupting other threads' syscalls
+    let action = SigAction::new(
+        SigHandler::Handler(on_terminate),
+        SaFlags::SA_RESTART,
+        SigSet::empty(),
+    );
+    unsafe { sigaction(Signal::SIGTERM, &action) }.context("Failed to handle SIGTERM")?;
+
+    std::thread::spawn(move || {
+        let mut byte = [0];
+        if File::from(read).read_exact(&mut byte).is_ok() {
+            commands.send(Command::Quit);
+        }
+    });
+    Ok(())
+}
+
+/// Fallback for a daemon that doesn't answer `quit`: signal the PID it recorded.
+/// Returns false if there was no live nicotine process to stop.
+pub fn kill_recorded_daemon() -> Result<bool> {
+    let Some(raw_pid) = runtime::read_pid_file() else {
+        return Ok(false);
+    };
+
+    // The PID may have been reused since the file was written; only touch our own binary
+    let comm = fs::read_to_string(format!("/proc/{}/comm", raw_pid)).unwrap_or_default();
+    if comm.trim() != "nicotine" {
+        let _ = fs::remove_file(runtime::pid_path());
+        return Ok(false);
+    }
+
+    let pid = Pid::from_raw(raw_pid);
+    kill(pid, Signal::SIGTERM).context("Failed to signal daemon")?;
+
+    // Give it a moment to exit before forcing it
+    for _ in 0..20 {
+        std::thread::sleep(Duration::from_millis(100));
+        if kill(pid, None).is_err() {
+            return Ok(true);
+        }
+    }
+
+    kill(pid, Signal::SIGKILL).context("Failed to kill daemon")?;
+    Ok(true)
+}
+
+fn connect() -> Result<UnixStream> {
+    let socket_path = runtime::socket_path();
+    if !socket_path.exists() {
+        anyhow::bail!("Daemon not running. Start with: nicotine start");
+    }
+
+    Ok(UnixStream::connect(socket_path)?)
+}
+
+/// Open a `subscribe` connection and return the stream of events pushed by the daemon
+pub fn subscribe() -> Result<EventStream> {
+    let mut stream = connect()?;
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+    stream.write_all(ipc::encode(&Request::new(Command::Subscribe))?.as_bytes())?;
+    stream.flush()?;
+
+    // The first line acknowledges the subscription; events follow with no deadline
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .context("Daemon did not respond")?;
+    let response: Response =
+        serde_json::from_str(line.trim()).context("Invalid response from daemon")?;
+    response.into_result()?;
+
+    reader.get_ref().set_read_timeout(None)?;
+    Ok(EventStream::new(reader))
+}
+
+/// Send a command to the running daemon and wait for its response.
+/// Returns an error only if the daemon could not be reached or didn't answer;
+/// a command that failed inside the daemon comes back as `Response::Error`.
+pub fn send_command(command: Command) -> Result<Response> {
+    let mut stream = connect()?;
+    stream.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+    stream.write_all(ipc::encode(&Request::new(command))?.as_bytes())?;
+    stream.flush()?;
+
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .context("Daemon did not respond")?;
+
+    serde_json::from_str(line.trim()).context("Invalid response from daemon")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(command: Command) -> Job {
+        Job {
+            command,
+            reply: None,
+        }
+    }
+
+    fn work(batches: Vec<Batch>) -> Vec<Work> {
+        batches.into_iter().map(|b| b.work).collect()
+    }
+
+    #[test]
+    fn test_coalesce_merges_cycle_presses() {
+        let jobs = vec![
+            job(Command::Forward),
+            job(Command::Forward),
+            job(Command::Backward),
+            job(Command::Forward),
+        ];
+        assert_eq!(work(coalesce(jobs)), vec![Work::Cycle(2)]);
+    }
+
+    #[test]
+    fn test_coalesce_keeps_last_switch() {
+        let jobs = vec![
+            job(Command::Switch(1)),
+            job(Command::Switch(3)),
+            job(Command::Switch(2)),
+        ];
+        assert_eq!(work(coalesce(jobs)), vec![Work::Run(Command::Switch(2))]);
+    }
+
+    #[test]
+    fn test_coalesce_preserves_order_across_other_commands() {
+        let jobs = vec![
+            job(Command::Forward),
+            job(Command::Stack),
+            job(Command::Backward),
+            job(Command::Backward),
+            job(Command::Switch(1)),
+            job(Command::Status),
+            job(Command::Status),
+        ];
+        assert_eq!(
+            work(coalesce(jobs)),
+            vec![
+                Work::Cycle(1),
+                Work::Run(Command::Stack),
+                Work::Cycle(-2),
+                Work::Run(Command::Switch(1)),
+                Work::Run(Command::Status),
+                Work::Run(Command::Status),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_coalesced_jobs_all_get_replies() {
+        let (tx_a, rx_a) = mpsc::channel();
+        let (tx_b, rx_b) = mpsc::channel();
+        let jobs = vec![
+            Job {
+                command: Command::Forward,
+                reply: Some(tx_a),
+            },
+            job(Command::Forward),
+            Job {
+                command: Command::Backward,
+                reply: Some(tx_b),
+            },
+        ];
+
+        let batches = coalesce(jobs);
+        assert_eq!(batches.len(), 1);
+        for reply in &batches[0].replies {
+            reply.send(Response::ok()).unwrap();
+        }
+        assert_eq!(rx_a.try_recv().unwrap(), Response::ok());
+        assert_eq!(rx_b.try_recv().unwrap(), Response::ok());
+    }
+
+    #[test]
+    fn test_replaced_switches_are_superseded() {
+        let (tx_2, rx_2) = mpsc::channel();
+        let (tx_3, rx_3) = mpsc::channel();
+        let jobs = vec![
+            Job {
+                command: Command::Switch(2),
+                reply: Some(tx_2),
+            },
+            Job {
+                command: Command::Switch(3),
+                reply: Some(tx_3),
+            },
+        ];
+
+        let batches = coalesce(jobs);
+        assert_eq!(batches.len(), 1);
+        for reply in &batches[0].superseded {
+            reply
+                .send(Response::error(ErrorCode::Superseded, ""))
+                .unwrap();
+        }
+        for reply in &batches[0].replies {
+            reply.send(Response::ok()).unwrap();
+        }
+        // Only `nicotine 3` hears how the switch went
+        assert_eq!(
+            rx_2.try_recv().unwrap(),
+            Response::error(ErrorCode::Superseded, "")
+        );
+        assert_eq!(rx_3.try_recv().unwrap(), Response::ok());
+    }
+
+    #[test]
+    fn test_dropped_reply_is_not_a_timeout() {
+        let (tx, rx) = mpsc::channel::<Job>();
+        // A worker that drops the job unanswered, as happens behind a quit
+        std::thread::spawn(move || drop(rx.recv()));
+
+        let response = CommandSender(tx).request(Command::Status);
+        assert_eq!(
+            response,
+            Response::error(ErrorCode::Unavailable, "Daemon is shutting down")
+        );
+    }
+
+    #[test]
+    fn test_sigterm_queues_quit() {
+        let (tx, rx) = mpsc::channel::<Job>();
+        spawn_signal_listener(CommandSender(tx)).unwrap();
+
+        nix::sys::signal::raise(Signal::SIGTERM).unwrap();
+
+        let job = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(job.command, Command::Quit);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::window_manager::WindowId;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+/// State changes pushed to `subscribe` clients, one JSON object per line
+///
+/// Example: `{"event":"focus_changed","index":2,"window_id":65011720,"character":"Alt One"}`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    FocusChanged {
+        /// 1-based position in the client list, as `nicotine status` numbers it
+        index: usize,
+        window_id: WindowId,
+        character: String,
+    },
+    ClientAdded {
+        /// 1-based position the client was added at
+        index: usize,
+        window_id: WindowId,
+        character: String,
+    },
+    ClientRemoved {
+        window_id: WindowId,
+        character: String,
+    },
+    Stacked {
+        count: usize,
+    },
+    ConfigReloaded,
+    ConfigError {
+        message: String,
+    },
+}
+
+/// Fan-out of daemon events to any number of subscribers
+pub struct EventBus {
+    subscribers: Mutex<Vec<Sender<Event>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self {
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    /// Send an event to every subscriber, dropping the ones that have gone away
+    pub fn publish(&self, event: Event) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(event.clone()).is_ok());
+    }
+}
+
+/// Client side of a `subscribe` connection
+pub struct EventStream {
+    reader: BufReader<UnixStream>,
+}
+
+impl EventStream {
+    pub fn new(reader: BufReader<UnixStream>) -> Self {
+        Self { reader }
+    }
+}
+
+impl Iterator for EventStream {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => None, // Daemon closed the connection
+            Ok(_) => Some(serde_json::from_str(line.trim()).context("Invalid event from daemon")),
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_publish_reaches_all_subscribers() {
+        let bus = EventBus::new();
+        let a = bus.subscribe();
+        let b = bus.subscribe();
+
+        bus.publish(Event::Stacked { count: 3 });
+
+        assert_eq!(a.try_recv().unwrap(), Event::Stacked { count: 3 });
+        assert_eq!(b.try_recv().unwrap(), Event::Stacked { count: 3 });
+    }
+
+    #[test]
+    fn test_dropped_subscribers_are_pruned() {
+        let bus = EventBus::new();
+        let kept = bus.subscribe();
+        drop(bus.subscribe());
+
+        bus.publish(Event::ConfigReloaded);
+
+        assert_eq!(bus.subscribers.lock().unwrap().len(), 1);
+        assert_eq!(kept.try_recv().unwrap(), Event::ConfigReloaded);
+    }
+
+    #[test]
+    fn test_event_encoding() {
+        let event = Event::FocusChanged {
+            index: 1,
+            window_id: WindowId(42),
+            character: "Alt One".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            "{\"event\":\"focus_changed\",\"index\":1,\"window_id\":42,\"character\":\"Alt One\"}"
+        );
+    }
+
+    #[test]
+    fn test_event_stream_reads_lines() {
+        let (mut server, client) = UnixStream::pair().unwrap();
+        writeln!(server, "{{\"event\":\"stacked\",\"count\":2}}").unwrap();
+        writeln!(server, "{{\"event\":\"config_reloaded\"}}").unwrap();
+        drop(server);
+
+        let events: Vec<Event> = EventStream::new(BufReader::new(client))
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            vec![Event::Stacked { count: 2 }, Event::ConfigReloaded]
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+
+/// UUID of the companion extension, also the directory it's installed into
+pub const EXTENSION_UUID: &str = "nicotine@crazydisi.github.io";
+
+const EXTENSION_FILES: &[(&str, &str)] = &[
+    (
+        "metadata.json",
+        include_str!("../assets/gnome-extension/metadata.json"),
+    ),
+    (
+        "extension.js",
+        include_str!("../assets/gnome-extension/extension.js"),
+    ),
+];
+
+/// `org.nicotine.Shell`, served by the companion GNOME Shell extension
+#[zbus::proxy(
+    interface = "org.nicotine.Shell",
+    default_service = "org.nicotine.Shell",
+    default_path = "/org/nicotine/Shell"
+)]
+pub trait Shell {
+    /// Every normal window as (id, title)
+    fn list_windows(&self) -> zbus::Result<Vec<(u64, String)>>;
+
+    fn active_window(&self) -> zbus::Result<u64>;
+
+    fn activate(&self, id: u64) -> zbus::Result<()>;
+
+    fn minimize(&self, id: u64) -> zbus::Result<()>;
+
+    fn unminimize(&self, id: u64) -> zbus::Result<()>;
+
+    /// Position and size of a window's frame as (x, y, width, height)
+    fn frame_rect(&self, id: u64) -> zbus::Result<(i32, i32, i32, i32)>;
+
+    fn move_resize(&self, id: u64, x: i32, y: i32, width: i32, height: i32) -> zbus::Result<()>;
+
+    /// Emitted when a window opens or closes, focus moves or a title changes
+    #[zbus(signal)]
+    fn windows_changed(&self) -> zbus::Result<()>;
+}
+
+/// Write the bundled extension into the user's GNOME Shell extensions directory
+pub fn install_extension() -> Result<()> {
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .context("Neither XDG_DATA_HOME nor HOME is set")?;
+    let dir = data_dir.join("gnome-shell/extensions").join(EXTENSION_UUID);
+
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    for (name, contents) in EXTENSION_FILES {
+        fs::write(dir.join(name), contents).with_context(|| format!("Failed to write {}", name))?;
+    }
+
+    println!("Installed GNOME Shell extension to {}", dir.display());
+    println!("Log out and back in, then enable it with:");
+    println!("  gnome-extensions enable {}", EXTENSION_UUID);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_config;
+    use crate::wayland_backends::GnomeManager;
+    use crate::window_manager::{WindowId, WindowManager};
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex};
+    use zbus::blocking::connection::Builder;
+    use zbus::blocking::Connection;
+
+    /// Stand-in for the extension: a window list in stacking order, like
+    /// Mutter's, with every call recorded as e.g. `"Activate 7"`
+    struct MockShell {
+        windows: Mutex<Vec<(u64, String)>>,
+        active: u64,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[zbus::interface(name = "org.nicotine.Shell")]
+    impl MockShell {
+        fn list_windows(&self) -> Vec<(u64, String)> {
+            self.windows.lock().unwrap().clone()
+        }
+
+        fn active_window(&self) -> u64 {
+            self.active
+        }
+
+        fn activate(&self, id: u64) {
+            // Activating raises the window to the top of the stack
+            let mut windows = self.windows.lock().unwrap();
+            if let Some(i) = windows.iter().position(|(window, _)| *window == id) {
+                let window = windows.remove(i);
+                windows.push(window);
+            }
+            self.calls.lock().unwrap().push(format!("Activate {}", id));
+        }
+
+        fn minimize(&self, id: u64) {
+            self.calls.lock().unwrap().push(format!("Minimize {}", id));
+        }
+
+        fn unminimize(&self, id: u64) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("Unminimize {}", id));
+        }
+
+        /// Every window sits at the stacking position with the EVE client size
+        #[zbus(out_args("x", "y", "width", "height"))]
+        fn frame_rect(&self, _id: u64) -> (i32, i32, i32, i32) {
+            (441, 0, 1037, 1080)
+        }
+
+        fn move_resize(&self, id: u64, x: i32, y: i32, width: i32, height: i32) {
+            self.calls.lock().unwrap().push(format!(
+                "MoveResize {} {} {} {} {}",
+                id, x, y, width, height
+            ));
+        }
+    }
+
+    /// A proxy talking to `shell` over a peer-to-peer connection; the server
+    /// end is returned so it stays alive for the test
+    fn mock_shell(shell: MockShell) -> (ShellProxyBlocking<'static>, Connection) {
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+
+        let server = std::thread::spawn(move || {
+            Builder::async_io_unix_stream(server_stream)
+                .server(zbus::Guid::generate())?
+                .p2p()
+                .serve_at("/org/nicotine/Shell", shell)?
+                .build()
+        });
+        let client = Builder::async_io_unix_stream(client_stream)
+            .p2p()
+            .build()
+            .unwrap();
+        let server = server.join().unwrap().unwrap();
+
+        let proxy = ShellProxyBlocking::builder(&client)
+            .cache_properties(zbus::proxy::CacheProperties::No)
+            .build()
+            .unwrap();
+        (proxy, server)
+    }
+
+    #[test]
+    fn test_extension_files_are_bundled() {
+        let metadata: serde_json::Value = serde_json::from_str(EXTENSION_FILES[0].1).unwrap();
+        assert_eq!(metadata["uuid"], EXTENSION_UUID);
+        assert!(EXTENSION_FILES[1].1.contains("org.nicotine.Shell"));
+    }
+
+    #[test]
+    fn test_proxy_round_trip() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let (proxy, _server) = mock_shell(MockShell {
+            windows: Mutex::new(vec![(7, "EVE - Alpha".to_string())]),
+            active: 7,
+            calls: Arc::clone(&calls),
+        });
+
+        assert_eq!(
+            proxy.list_windows().unwrap(),
+            vec![(7, "EVE - Alpha".to_string())]
+        );
+        assert_eq!(proxy.active_window().unwrap(), 7);
+        proxy.move_resize(7, 441, 0, 1037, 1080).unwrap();
+        assert_eq!(*calls.lock().unwrap(), vec!["MoveResize 7 441 0 1037 1080"]);
+    }
+
+    #[test]
+    fn test_gnome_lists_and_stacks_through_extension() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let (shell, _server) = mock_shell(MockShell {
+            windows: Mutex::new(vec![
+                (7, "EVE - Alpha".to_string()),
+                (8, "Terminal".to_string()),
+                (9, "EVE - Beta".to_string()),
+            ]),
+            active: 9,
+            calls: Arc::clone(&calls),
+        });
+        let gnome = GnomeManager::with_proxy(shell);
+
+        let windows = gnome.get_eve_windows().unwrap();
+        assert_eq!(
+            windows.iter().map(|w| w.id).collect::<Vec<_>>(),
+            vec![WindowId(7), WindowId(9)]
+        );
+        assert_eq!(gnome.get_active_window().unwrap(), WindowId(9));
+
+        gnome.stack_windows(&windows, &test_config()).unwrap();
+        gnome.minimize_window(WindowId(7)).unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "MoveResize 7 441 0 1037 1080",
+                "MoveResize 9 441 0 1037 1080",
+                "Minimize 7",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gnome_moves_windows_at_their_current_size() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let (shell, _server) = mock_shell(MockShell {
+            windows: Mutex::new(vec![(7, "Nicotine".to_string())]),
+            active: 7,
+            calls: Arc::clone(&calls),
+        });
+        let gnome = GnomeManager::with_proxy(shell);
+
+        assert!(gnome.capabilities().move_windows);
+        assert_eq!(gnome.window_position(WindowId(7)).unwrap(), Some((441, 0)));
+        gnome.move_window(WindowId(7), 20, 30).unwrap();
+        assert_eq!(*calls.lock().unwrap(), vec!["MoveResize 7 20 30 1037 1080"]);
+    }
+
+    #[test]
+    fn test_gnome_order_survives_activation() {
+        // Listed in stacking order, topmost last
+        let (shell, _server) = mock_shell(MockShell {
+            windows: Mutex::new(vec![
+                (9, "EVE - Beta".to_string()),
+                (7, "EVE - Alpha".to_string()),
+                (11, "EVE - Gamma".to_string()),
+            ]),
+            active: 11,
+            calls: Arc::new(Mutex::new(Vec::new())),
+        });
+        let gnome = GnomeManager::with_proxy(shell);
+        let ids = |gnome: &GnomeManager| {
+            gnome
+                .get_eve_windows()
+                .unwrap()
+                .iter()
+                .map(|w| w.id)
+                .collect::<Vec<_>>()
+        };
+
+        let before = ids(&gnome);
+        assert_eq!(before, vec![WindowId(7), WindowId(9), WindowId(11)]);
+        gnome.activate_window(WindowId(7)).unwrap();
+        gnome.activate_window(WindowId(9)).unwrap();
+        assert_eq!(ids(&gnome), before);
+    }
+}
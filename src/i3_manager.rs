@@ -0,0 +1,108 @@
+use crate::config::Config;
+use crate::wayland_backends::SwayManager;
+use crate::window_manager::{Backend, Capabilities, EveWindow, WindowId, WindowManager};
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+
+/// i3 on X11, driven over its IPC socket
+///
+/// Moving windows with `configure_window` fights i3's tiling and it ignores
+/// `WM_CHANGE_STATE`, so this uses the same IPC protocol and commands as the
+/// sway backend: EVE clients are floated, placed, focused and hidden in the
+/// scratchpad through i3 itself. Window IDs are i3 container IDs.
+pub struct I3Manager {
+    sway: SwayManager,
+}
+
+impl I3Manager {
+    pub fn new() -> Result<Self> {
+        let socket = crate::x11_manager::i3_socket_path()
+            .context("i3 IPC socket not found. Make sure you're running i3")?;
+        Self::with_socket(socket)
+    }
+
+    pub fn with_socket(path: impl Into<PathBuf>) -> Result<Self> {
+        Ok(Self {
+            sway: SwayManager::with_socket(path)?,
+        })
+    }
+}
+
+impl WindowManager for I3Manager {
+    fn backend(&self) -> Backend {
+        Backend::I3
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.sway.capabilities()
+    }
+
+    fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
+        self.sway.get_eve_windows()
+    }
+
+    fn activate_window(&self, window_id: WindowId) -> Result<()> {
+        self.sway.activate_window(window_id)
+    }
+
+    fn stack_windows(&self, windows: &[EveWindow], config: &Config) -> Result<()> {
+        self.sway.stack_windows(windows, config)
+    }
+
+    fn get_active_window(&self) -> Result<WindowId> {
+        self.sway.get_active_window()
+    }
+
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowId>> {
+        self.sway.find_window_by_title(title)
+    }
+
+    fn move_window(&self, window_id: WindowId, x: i32, y: i32) -> Result<()> {
+        self.sway.move_window(window_id, x, y)
+    }
+
+    fn minimize_window(&self, window_id: WindowId) -> Result<()> {
+        self.sway.minimize_window(window_id)
+    }
+
+    fn restore_window(&self, window_id: WindowId) -> Result<()> {
+        self.sway.restore_window(window_id)
+    }
+
+    fn watch_windows(&self, notify: Sender<()>) -> Result<bool> {
+        self.sway.watch_windows(notify)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::serve;
+
+    #[test]
+    fn test_lists_x11_clients_from_i3_tree() {
+        // i3 nodes carry window_properties rather than sway's app_id
+        let tree = r#"{"id":1,"type":"root","nodes":[{"id":2,"type":"output","nodes":[
+            {"id":3,"type":"workspace","nodes":[
+                {"id":94,"type":"con","name":"EVE - Alpha","window":41943047,
+                 "window_properties":{"class":"steam_app_8500","title":"EVE - Alpha"},"nodes":[]},
+                {"id":95,"type":"con","name":"EVE","window":null,"window_properties":null,"nodes":[]}
+            ],"floating_nodes":[
+                {"id":96,"type":"floating_con","name":"EVE - Beta","window":41943048,
+                 "window_properties":{"class":"steam_app_8500","title":"EVE - Beta"},"nodes":[]}
+            ]}]}]}"#;
+        let (path, _requests) = serve("i3-tree", vec![(4, tree.into())]);
+        let i3 = I3Manager::with_socket(&path).unwrap();
+
+        let windows = i3.get_eve_windows().unwrap();
+        assert_eq!(
+            windows
+                .iter()
+                .map(|w| (w.id, w.title.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(WindowId(94), "Alpha"), (WindowId(96), "Beta")]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
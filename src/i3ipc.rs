@@ -0,0 +1,342 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Every i3/sway IPC message starts with this, followed by the payload length
+/// and message type as native-endian u32s
+const MAGIC: &[u8; 6] = b"i3-ipc";
+const HEADER_LEN: usize = MAGIC.len() + 8;
+
+/// Replies to events have the high bit of the message type set
+const EVENT_BIT: u32 = 1 << 31;
+
+/// How long to wait on the compositor before giving up on a request
+const IO_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum MessageType {
+    RunCommand = 0,
+    Subscribe = 2,
+    GetTree = 4,
+}
+
+/// Connection to an i3 or sway IPC socket
+///
+/// Requests share one connection, which is reopened if the compositor dropped
+/// it (e.g. after a config reload). A request is only sent again when it
+/// couldn't be written at all; once sent, a command may have run.
+pub struct I3Ipc {
+    path: PathBuf,
+    stream: Mutex<Option<UnixStream>>,
+}
+
+impl I3Ipc {
+    pub fn connect(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let stream = open(&path)?;
+        Ok(Self {
+            path,
+            stream: Mutex::new(Some(stream)),
+        })
+    }
+
+    /// The layout tree, as returned by GET_TREE
+    pub fn get_tree(&self) -> Result<Value> {
+        let reply = self.request(MessageType::GetTree, b"")?;
+        serde_json::from_slice(&reply).context("Failed to parse window tree")
+    }
+
+    /// Run one or more commands (separated by `;`) in a single message,
+    /// failing with the first error the compositor reports
+    pub fn run_command(&self, command: &str) -> Result<()> {
+        let reply = self.request(MessageType::RunCommand, command.as_bytes())?;
+        let results: Vec<Value> =
+            serde_json::from_slice(&reply).context("Failed to parse command reply")?;
+
+        for result in &results {
+            if result.get("success").and_then(|s| s.as_bool()) != Some(true) {
+                let error = result
+                    .get("error")
+                    .and_then(|e| e.as_str())
+                    .unwrap_or("unknown error");
+                anyhow::bail!("Command '{}' failed: {}", command, error);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Open a dedicated connection that receives the given event types
+    pub fn subscribe(&self, events: &[&str]) -> Result<I3EventStream> {
+        let mut stream = open(&self.path)?;
+        let payload = serde_json::to_vec(events)?;
+        write_message(&mut stream, MessageType::Subscribe as u32, &payload)?;
+
+        let (_, reply) = read_message(&mut stream)?;
+        let reply: Value = serde_json::from_slice(&reply).context("Invalid subscribe reply")?;
+        if reply.get("success").and_then(|s| s.as_bool()) != Some(true) {
+            anyhow::bail!("Subscribing to {:?} was refused", events);
+        }
+
+        // Events can be far apart
+        stream.set_read_timeout(None)?;
+        Ok(I3EventStream { stream })
+    }
+
+    fn request(&self, message_type: MessageType, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut guard = self.stream.lock().unwrap();
+
+        if let Some(stream) = guard.as_mut() {
+            if write_message(stream, message_type as u32, payload).is_ok() {
+                let reply = read_reply(stream, message_type);
+                if reply.is_err() {
+                    // The connection may be out of step now; start afresh next time
+                    *guard = None;
+                }
+                return reply;
+            }
+            *guard = None;
+        }
+
+        // The compositor closed our connection before the request went out;
+        // send it on a fresh one
+        let mut stream = open(&self.path)?;
+        write_message(&mut stream, message_type as u32, payload)?;
+        let reply = read_reply(&mut stream, message_type)?;
+        *guard = Some(stream);
+        Ok(reply)
+    }
+}
+
+/// An i3/sway event: the event type (without the high bit) and its JSON body
+#[derive(Debug, Clone, PartialEq)]
+pub struct I3Event {
+    pub kind: u32,
+    pub payload: Value,
+}
+
+pub struct I3EventStream {
+    stream: UnixStream,
+}
+
+impl Iterator for I3EventStream {
+    type Item = Result<I3Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (kind, payload) = match read_message(&mut self.stream) {
+            Ok(message) => message,
+            // Compositor went away
+            Err(_) => return None,
+        };
+        Some(
+            serde_json::from_slice(&payload)
+                .context("Invalid event payload")
+                .map(|payload| I3Event {
+                    kind: kind & !EVENT_BIT,
+                    payload,
+                }),
+        )
+    }
+}
+
+fn open(path: &Path) -> Result<UnixStream> {
+    let stream = UnixStream::connect(path)
+        .with_context(|| format!("Failed to connect to IPC socket {}", path.display()))?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    Ok(stream)
+}
+
+fn read_reply(stream: &mut UnixStream, message_type: MessageType) -> Result<Vec<u8>> {
+    let (reply_type, reply) = read_message(stream)?;
+    if reply_type != message_type as u32 {
+        anyhow::bail!(
+            "Expected reply type {}, got {}",
+            message_type as u32,
+            reply_type
+        );
+    }
+    Ok(reply)
+}
+
+pub(crate) fn write_message(
+    writer: &mut impl Write,
+    message_type: u32,
+    payload: &[u8],
+) -> Result<()> {
+    let mut message = Vec::with_capacity(HEADER_LEN + payload.len());
+    message.extend_from_slice(MAGIC);
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&message_type.to_ne_bytes());
+    message.extend_from_slice(payload);
+    writer
+        .write_all(&message)
+        .context("Failed to send IPC message")
+}
+
+pub(crate) fn read_message(reader: &mut impl Read) -> Result<(u32, Vec<u8>)> {
+    let mut header = [0u8; HEADER_LEN];
+    reader
+        .read_exact(&mut header)
+        .context("Failed to read IPC reply")?;
+
+    if &header[..MAGIC.len()] != MAGIC {
+        anyhow::bail!("Invalid IPC reply header");
+    }
+    let length = u32::from_ne_bytes(header[6..10].try_into().unwrap()) as usize;
+    let message_type = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+
+    let mut payload = vec![0u8; length];
+    reader
+        .read_exact(&mut payload)
+        .context("Failed to read IPC reply")?;
+
+    Ok((message_type, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::serve;
+    use std::os::unix::net::UnixListener;
+
+    #[test]
+    fn test_message_framing() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, MessageType::GetTree as u32, b"{}").unwrap();
+        assert_eq!(&buffer[..6], b"i3-ipc");
+        assert_eq!(buffer.len(), HEADER_LEN + 2);
+
+        let (message_type, payload) = read_message(&mut buffer.as_slice()).unwrap();
+        assert_eq!(message_type, 4);
+        assert_eq!(payload, b"{}");
+    }
+
+    #[test]
+    fn test_rejects_bad_magic() {
+        let buffer = b"i4-ipc\0\0\0\0\0\0\0\0".to_vec();
+        assert!(read_message(&mut buffer.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_get_tree() {
+        let (path, requests) = serve("get-tree", vec![(4, "{\"id\":1,\"nodes\":[]}".into())]);
+        let ipc = I3Ipc::connect(&path).unwrap();
+
+        assert_eq!(ipc.get_tree().unwrap()["id"], 1);
+        assert_eq!(requests.recv().unwrap(), (4, String::new()));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_run_command_reports_failures() {
+        let (path, requests) = serve(
+            "run-command",
+            vec![
+                (0, "[{\"success\":true},{\"success\":true}]".into()),
+                (
+                    0,
+                    "[{\"success\":false,\"error\":\"No matching node\"}]".into(),
+                ),
+            ],
+        );
+        let ipc = I3Ipc::connect(&path).unwrap();
+
+        ipc.run_command("[con_id=1] focus; [con_id=2] focus")
+            .unwrap();
+        assert_eq!(
+            requests.recv().unwrap(),
+            (0, "[con_id=1] focus; [con_id=2] focus".to_string())
+        );
+
+        let err = ipc.run_command("[con_id=9] focus").unwrap_err();
+        assert!(err.to_string().contains("No matching node"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn bind(name: &str) -> (PathBuf, UnixListener) {
+        let path = std::env::temp_dir().join(format!(
+            "nicotine-test-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        (path, listener)
+    }
+
+    #[test]
+    fn test_reconnects_when_connection_was_closed() {
+        let (path, listener) = bind("reconnect");
+        let ipc = I3Ipc::connect(&path).unwrap();
+        // The compositor drops our connection, e.g. on a config reload
+        drop(listener.accept().unwrap());
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_message(&mut stream).unwrap();
+            write_message(&mut stream, 4, b"{\"id\":1}").unwrap();
+        });
+
+        assert_eq!(ipc.get_tree().unwrap()["id"], 1);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_sent_command_is_not_repeated() {
+        let (path, listener) = bind("no-repeat");
+        let ipc = I3Ipc::connect(&path).unwrap();
+        let client = std::thread::spawn(move || ipc.run_command("kill"));
+
+        // Take the command, then go away without answering
+        let (mut stream, _) = listener.accept().unwrap();
+        read_message(&mut stream).unwrap();
+        drop(stream);
+
+        assert!(client.join().unwrap().is_err());
+        listener.set_nonblocking(true).unwrap();
+        assert!(listener.accept().is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_subscribe_streams_events() {
+        let path = std::env::temp_dir().join(format!(
+            "nicotine-test-subscribe-{}.sock",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        std::thread::spawn(move || {
+            // One connection for `I3Ipc::connect`, one for the subscription
+            let _idle = listener.accept().unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            let (message_type, payload) = read_message(&mut stream).unwrap();
+            assert_eq!(message_type, MessageType::Subscribe as u32);
+            assert_eq!(payload, b"[\"window\"]");
+            write_message(&mut stream, 2, b"{\"success\":true}").unwrap();
+            write_message(&mut stream, EVENT_BIT | 3, b"{\"change\":\"focus\"}").unwrap();
+        });
+
+        let ipc = I3Ipc::connect(&path).unwrap();
+        let events: Vec<I3Event> = ipc
+            .subscribe(&["window"])
+            .unwrap()
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            vec![I3Event {
+                kind: 3,
+                payload: serde_json::json!({"change": "focus"}),
+            }]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,368 @@
+use crate::cycle_state::{CycleState, SwitchError};
+use crate::daemon::Command;
+use crate::window_manager::{Backend, Capabilities, WindowId};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Version of the JSON protocol spoken over the daemon socket.
+/// Bump this whenever a request or response changes shape.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// A single request line sent by a client
+///
+/// Example: `{"version":1,"command":{"switch":3}}`
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Request {
+    pub version: u32,
+    pub command: Command,
+}
+
+impl Request {
+    pub fn new(command: Command) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            command,
+        }
+    }
+}
+
+/// Machine-readable error categories returned to clients
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request line could not be parsed
+    InvalidRequest,
+    /// The client speaks a different protocol version
+    UnsupportedVersion,
+    /// Switch target is beyond the configured characters or open windows
+    OutOfRange,
+    /// The character for the requested slot is not logged in
+    CharacterNotFound,
+    /// The window manager backend failed to carry out the command
+    WindowManager,
+    /// The connecting process belongs to a different user
+    PermissionDenied,
+    /// The daemon is shutting down or timed out waiting for the window manager
+    Unavailable,
+    /// config.toml or characters.txt failed validation on reload
+    InvalidConfig,
+    /// A switch queued right behind this one was carried out instead
+    Superseded,
+}
+
+/// One client as the daemon sees it, reported by `status`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ClientStatus {
+    /// Position in the cycle order (1-based, what `nicotine N` targets without characters.txt)
+    pub index: usize,
+    pub window_id: WindowId,
+    pub character: String,
+    pub active: bool,
+    /// Line number in characters.txt (1-based), if the character is listed there
+    pub slot: Option<usize>,
+}
+
+/// Snapshot of the daemon's cycle state
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StatusReport {
+    pub pid: u32,
+    pub backend: Backend,
+    pub capabilities: Capabilities,
+    pub clients: Vec<ClientStatus>,
+    /// characters.txt as the daemon switches by, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub character_order: Option<Vec<String>>,
+}
+
+impl StatusReport {
+    pub fn new(state: &CycleState, backend: Backend, capabilities: Capabilities) -> Self {
+        let character_order = state.character_order();
+        let current_index = state.get_current_index();
+        let clients = state
+            .get_windows()
+            .iter()
+            .enumerate()
+            .map(|(i, window)| ClientStatus {
+                index: i + 1,
+                window_id: window.id,
+                character: window.title.clone(),
+                active: i == current_index,
+                slot: character_order
+                    .and_then(|order| order.iter().position(|name| *name == window.title))
+                    .map(|slot| slot + 1),
+            })
+            .collect();
+
+        Self {
+            pid: std::process::id(),
+            backend,
+            capabilities,
+            clients,
+            character_order: character_order.map(<[String]>::to_vec),
+        }
+    }
+}
+
+/// A single response line sent back by the daemon
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    Ok {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        data: Option<StatusReport>,
+    },
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Response::Ok { data: None }
+    }
+
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        Response::Error {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Turn an error response back into an `Err` carrying the daemon's message
+    pub fn into_result(self) -> Result<()> {
+        match self {
+            Response::Ok { .. } => Ok(()),
+            Response::Error { message, .. } => Err(anyhow::anyhow!(message)),
+        }
+    }
+
+    /// Extract the status report from a response to `Command::Status`
+    pub fn into_status(self) -> Result<StatusReport> {
+        match self {
+            Response::Ok { data: Some(report) } => Ok(report),
+            Response::Ok { data: None } => anyhow::bail!("Daemon sent no status"),
+            Response::Error { message, .. } => Err(anyhow::anyhow!(message)),
+        }
+    }
+
+    /// Map a command failure to a structured error response
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        let code = match err.downcast_ref::<SwitchError>() {
+            Some(SwitchError::CharacterNotFound(_)) => ErrorCode::CharacterNotFound,
+            Some(_) => ErrorCode::OutOfRange,
+            None => ErrorCode::WindowManager,
+        };
+        Response::error(code, format!("{:#}", err))
+    }
+}
+
+/// Parse a request line received by the daemon.
+/// Plain-text commands from older clients (e.g. `switch:3`) are still accepted.
+pub fn parse_request(line: &str) -> Result<Request, Response> {
+    let line = line.trim();
+
+    if !line.starts_with('{') {
+        return Command::from_str(line).map(Request::new).ok_or_else(|| {
+            Response::error(
+                ErrorCode::InvalidRequest,
+                format!("Unknown command: {}", line),
+            )
+        });
+    }
+
+    let request: Request = serde_json::from_str(line).map_err(|e| {
+        Response::error(ErrorCode::InvalidRequest, format!("Invalid request: {}", e))
+    })?;
+
+    if request.version != PROTOCOL_VERSION {
+        return Err(Response::error(
+            ErrorCode::UnsupportedVersion,
+            format!(
+                "Unsupported protocol version {} (daemon speaks {})",
+                request.version, PROTOCOL_VERSION
+            ),
+        ));
+    }
+
+    Ok(request)
+}
+
+/// Encode a message as a single newline-terminated JSON line
+pub fn encode<T: Serialize>(message: &T) -> Result<String> {
+    let mut line = serde_json::to_string(message).context("Failed to encode message")?;
+    line.push('\n');
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::window_manager::EveWindow;
+
+    #[test]
+    fn test_request_round_trip() {
+        let line = encode(&Request::new(Command::Switch(3))).unwrap();
+        assert_eq!(line, "{\"version\":1,\"command\":{\"switch\":3}}\n");
+        assert_eq!(
+            parse_request(&line).unwrap(),
+            Request::new(Command::Switch(3))
+        );
+    }
+
+    #[test]
+    fn test_unit_command_encoding() {
+        let line = encode(&Request::new(Command::Forward)).unwrap();
+        assert_eq!(line, "{\"version\":1,\"command\":\"forward\"}\n");
+    }
+
+    #[test]
+    fn test_window_command_encoding() {
+        let command = Command::AssignSlot {
+            window_id: WindowId(7),
+            slot: 2,
+        };
+        let line = encode(&Request::new(command.clone())).unwrap();
+        assert_eq!(
+            line,
+            "{\"version\":1,\"command\":{\"assign_slot\":{\"window_id\":7,\"slot\":2}}}\n"
+        );
+        assert_eq!(parse_request(&line).unwrap(), Request::new(command));
+    }
+
+    #[test]
+    fn test_legacy_plain_text_request() {
+        assert_eq!(
+            parse_request("switch:2\n").unwrap(),
+            Request::new(Command::Switch(2))
+        );
+        assert_eq!(
+            parse_request("backward").unwrap(),
+            Request::new(Command::Backward)
+        );
+    }
+
+    #[test]
+    fn test_unknown_command_is_invalid() {
+        match parse_request("teleport") {
+            Err(Response::Error { code, .. }) => assert_eq!(code, ErrorCode::InvalidRequest),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_wrong_version_is_rejected() {
+        match parse_request("{\"version\":99,\"command\":\"forward\"}") {
+            Err(Response::Error { code, .. }) => {
+                assert_eq!(code, ErrorCode::UnsupportedVersion)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_switch_errors_map_to_codes() {
+        let err: anyhow::Error = SwitchError::CharacterNotFound("Alpha".to_string()).into();
+        assert_eq!(
+            Response::from_error(&err),
+            Response::error(
+                ErrorCode::CharacterNotFound,
+                "Character 'Alpha' not found in active windows"
+            )
+        );
+
+        let err: anyhow::Error = SwitchError::WindowOutOfRange {
+            target: 4,
+            windows: 2,
+        }
+        .into();
+        match Response::from_error(&err) {
+            Response::Error { code, .. } => assert_eq!(code, ErrorCode::OutOfRange),
+            other => panic!("unexpected response: {:?}", other),
+        }
+
+        let err = anyhow::anyhow!("swaymsg failed");
+        match Response::from_error(&err) {
+            Response::Error { code, .. } => assert_eq!(code, ErrorCode::WindowManager),
+            other => panic!("unexpected response: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_response_encoding() {
+        assert_eq!(encode(&Response::ok()).unwrap(), "{\"status\":\"ok\"}\n");
+        let line = encode(&Response::error(ErrorCode::OutOfRange, "nope")).unwrap();
+        assert_eq!(
+            line,
+            "{\"status\":\"error\",\"code\":\"out_of_range\",\"message\":\"nope\"}\n"
+        );
+        let decoded: Response = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(decoded, Response::error(ErrorCode::OutOfRange, "nope"));
+    }
+
+    #[test]
+    fn test_status_report_from_state() {
+        let mut state = CycleState::new();
+        state.update_windows(vec![
+            EveWindow {
+                id: WindowId(100),
+                title: "Gamma".to_string(),
+            },
+            EveWindow {
+                id: WindowId(200),
+                title: "Alpha".to_string(),
+            },
+        ]);
+        state.set_current_index(1);
+
+        let order = vec!["Alpha".to_string(), "Beta".to_string()];
+        state.set_character_order(Some(order.clone()));
+        let report = StatusReport::new(&state, Backend::X11, Capabilities::default());
+
+        assert_eq!(
+            report.clients,
+            vec![
+                ClientStatus {
+                    index: 1,
+                    window_id: WindowId(100),
+                    character: "Gamma".to_string(),
+                    active: false,
+                    slot: None,
+                },
+                ClientStatus {
+                    index: 2,
+                    window_id: WindowId(200),
+                    character: "Alpha".to_string(),
+                    active: true,
+                    slot: Some(1),
+                },
+            ]
+        );
+        assert_eq!(report.character_order, Some(order));
+    }
+
+    #[test]
+    fn test_status_response_round_trip() {
+        let response = Response::Ok {
+            data: Some(StatusReport {
+                pid: 42,
+                backend: Backend::Sway,
+                capabilities: Capabilities {
+                    events: true,
+                    ..Capabilities::default()
+                },
+                clients: vec![],
+                character_order: None,
+            }),
+        };
+        let line = encode(&response).unwrap();
+        assert_eq!(
+            line,
+            "{\"status\":\"ok\",\"data\":{\"pid\":42,\"backend\":\"sway\",\
+             \"capabilities\":{\"move_windows\":false,\"resize\":false,\"minimize\":false,\
+             \"events\":true,\"thumbnails\":false},\"clients\":[]}}\n"
+        );
+        let decoded: Response = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(decoded.into_status().unwrap().pid, 42);
+    }
+}
@@ -0,0 +1,471 @@
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use zbus::blocking::Connection;
+
+/// Object we serve for scripts to hand their results back through `callDBus`
+const BRIDGE_PATH: &str = "/org/nicotine/KWinBridge";
+
+/// How long to wait for a loaded script to call back
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Helpers available to every script. KWin 6 renamed clients to windows, so
+/// both APIs are handled. `__SERVICE__` and `__TOKEN__` are filled in per script.
+const SCRIPT_PRELUDE: &str = r#"// Generated by nicotine, unloaded again once it has replied
+const windows = workspace.windowList ? workspace.windowList() : workspace.clientList();
+
+function reply(data) {
+    callDBus(__SERVICE__, "/org/nicotine/KWinBridge", "org.nicotine.KWinBridge",
+        "Reply", "__TOKEN__", JSON.stringify(data));
+}
+
+function findWindow(id) {
+    return windows.find(w => String(w.internalId) === id);
+}
+
+function activeWindow() {
+    return workspace.windowList ? workspace.activeWindow : workspace.activeClient;
+}
+
+function activate(w) {
+    if (workspace.windowList) {
+        workspace.activeWindow = w;
+    } else {
+        workspace.activeClient = w;
+    }
+}
+
+"#;
+
+/// Helper for the long-lived watch script; `__SERVICE__` is filled in on load
+const WATCH_PRELUDE: &str = r#"// Generated by nicotine, stays loaded while the daemon runs
+function changed() {
+    callDBus(__SERVICE__, "/org/nicotine/KWinBridge", "org.nicotine.KWinBridge", "Changed");
+}
+
+"#;
+
+/// Plugin name of the watch script; only one daemon runs per session
+const WATCH_PLUGIN: &str = "nicotine-watch";
+
+type Pending = Arc<Mutex<HashMap<u32, Sender<String>>>>;
+type Watchers = Arc<Mutex<Vec<Sender<()>>>>;
+
+struct Bridge {
+    pending: Pending,
+    watchers: Watchers,
+}
+
+#[zbus::interface(name = "org.nicotine.KWinBridge")]
+impl Bridge {
+    /// Called by a script with the token it was generated with and its JSON result
+    fn reply(&self, token: String, data: String) {
+        let sender = token
+            .parse()
+            .ok()
+            .and_then(|token| self.pending.lock().unwrap().remove(&token));
+        if let Some(sender) = sender {
+            let _ = sender.send(data);
+        }
+    }
+
+    /// Called by the watch script whenever something it watches changes
+    fn changed(&self) {
+        self.watchers
+            .lock()
+            .unwrap()
+            .retain(|watcher| watcher.send(()).is_ok());
+    }
+}
+
+/// Runs one-off scripts inside KWin through `org.kde.kwin.Scripting`
+///
+/// KWin scripts can't be called into, so each request is written out as a
+/// script that reports back to our bridge object, loaded, run and unloaded.
+pub struct KWinScripting {
+    conn: Connection,
+    /// `org.kde.KWin` on the session bus; `None` on a peer-to-peer connection
+    destination: Option<&'static str>,
+    script_dir: PathBuf,
+    pending: Pending,
+    watchers: Watchers,
+    /// Whether the watch script is loaded and needs unloading on drop
+    watching: AtomicBool,
+    next_token: AtomicU32,
+}
+
+impl KWinScripting {
+    pub fn session() -> Result<Self> {
+        let conn = Connection::session().context("Failed to connect to the session D-Bus")?;
+        Self::new(
+            conn,
+            Some("org.kde.KWin"),
+            crate::runtime::ensure_runtime_dir()?,
+        )
+    }
+
+    pub fn new(
+        conn: Connection,
+        destination: Option<&'static str>,
+        script_dir: PathBuf,
+    ) -> Result<Self> {
+        let pending = Pending::default();
+        let watchers = Watchers::default();
+        conn.object_server()
+            .at(
+                BRIDGE_PATH,
+                Bridge {
+                    pending: Arc::clone(&pending),
+                    watchers: Arc::clone(&watchers),
+                },
+            )
+            .context("Failed to register KWin script bridge")?;
+
+        Ok(Self {
+            conn,
+            destination,
+            script_dir,
+            pending,
+            watchers,
+            watching: AtomicBool::new(false),
+            next_token: AtomicU32::new(1),
+        })
+    }
+
+    /// Run `body` as a KWin script and decode the value it passes to `reply()`
+    pub fn run<T: DeserializeOwned>(&self, body: &str) -> Result<T> {
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel();
+        self.pending.lock().unwrap().insert(token, tx);
+
+        let path = self.script_dir.join(format!("kwin-{}.js", token));
+        let plugin = format!("nicotine-{}", token);
+
+        let result = fs::write(&path, self.script(token, body))
+            .context("Failed to write KWin script")
+            .and_then(|_| self.load_and_run(&path, &plugin))
+            .and_then(|_| {
+                rx.recv_timeout(REPLY_TIMEOUT)
+                    .context("KWin script did not respond")
+            });
+
+        self.pending.lock().unwrap().remove(&token);
+        let _ = self.call(
+            "/Scripting",
+            "org.kde.kwin.Scripting",
+            "unloadScript",
+            &plugin,
+        );
+        let _ = fs::remove_file(&path);
+
+        serde_json::from_str(&result?).context("Invalid reply from KWin script")
+    }
+
+    /// Load `body` as a script that stays loaded, sending on `notify` each time
+    /// it calls `changed()`
+    pub fn watch(&self, body: &str, notify: Sender<()>) -> Result<()> {
+        self.watchers.lock().unwrap().push(notify);
+
+        // A daemon that didn't get to clean up may have left its watcher loaded
+        let _ = self.call(
+            "/Scripting",
+            "org.kde.kwin.Scripting",
+            "unloadScript",
+            &WATCH_PLUGIN,
+        );
+
+        // KWin may read the file after `run` returns, so it's kept until drop
+        let path = self.watch_script_path();
+        let script = WATCH_PRELUDE.replace("__SERVICE__", &self.service_literal()) + body;
+        fs::write(&path, script).context("Failed to write KWin script")?;
+        self.load_and_run(&path, WATCH_PLUGIN)?;
+        self.watching.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn watch_script_path(&self) -> PathBuf {
+        self.script_dir.join("kwin-watch.js")
+    }
+
+    fn script(&self, token: u32, body: &str) -> String {
+        SCRIPT_PRELUDE
+            .replace("__SERVICE__", &self.service_literal())
+            .replace("__TOKEN__", &token.to_string())
+            + body
+    }
+
+    /// JavaScript string literal for our bus name, for scripts to call back
+    fn service_literal(&self) -> String {
+        let service = self
+            .conn
+            .unique_name()
+            .map(|name| name.to_string())
+            .unwrap_or_default();
+        serde_json::to_string(&service).unwrap()
+    }
+
+    fn load_and_run(&self, path: &Path, plugin: &str) -> Result<()> {
+        let path = path.to_str().context("Script path is not valid UTF-8")?;
+        let reply = self.call(
+            "/Scripting",
+            "org.kde.kwin.Scripting",
+            "loadScript",
+            &(path, plugin),
+        )?;
+        let id: i32 = reply.body().deserialize()?;
+        if id < 0 {
+            anyhow::bail!("KWin refused to load script");
+        }
+
+        // KWin 6 exposes loaded scripts under /Scripting, KWin 5 at the root
+        self.call(
+            &format!("/Scripting/Script{}", id),
+            "org.kde.kwin.Script",
+            "run",
+            &(),
+        )
+        .or_else(|_| self.call(&format!("/{}", id), "org.kde.kwin.Script", "run", &()))
+        .context("Failed to run KWin script")?;
+        Ok(())
+    }
+
+    fn call<B>(&self, path: &str, interface: &str, method: &str, body: &B) -> Result<zbus::Message>
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        self.conn
+            .call_method(self.destination, path, Some(interface), method, body)
+            .with_context(|| format!("KWin D-Bus call {} failed", method))
+    }
+}
+
+impl Drop for KWinScripting {
+    fn drop(&mut self) {
+        if self.watching.load(Ordering::Relaxed) {
+            let _ = self.call(
+                "/Scripting",
+                "org.kde.kwin.Scripting",
+                "unloadScript",
+                &WATCH_PLUGIN,
+            );
+            let _ = fs::remove_file(self.watch_script_path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wayland_backends::KWinManager;
+    use crate::window_manager::WindowManager;
+    use std::os::unix::net::UnixStream;
+    use zbus::blocking::connection::Builder;
+
+    type Respond = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+    /// Stand-in for KWin's scripting service on a peer-to-peer connection.
+    /// `respond` maps the source of each script that's run to its reply.
+    struct MockKWin {
+        scripting: KWinScripting,
+        /// Source of every script loaded so far
+        scripts: Arc<Mutex<Vec<String>>>,
+        unloaded: Arc<Mutex<Vec<String>>>,
+        _server: Connection,
+    }
+
+    struct MockScripting {
+        scripts: Arc<Mutex<Vec<String>>>,
+        unloaded: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[zbus::interface(name = "org.kde.kwin.Scripting")]
+    impl MockScripting {
+        #[zbus(name = "loadScript")]
+        fn load_script(&self, path: String, _plugin: String) -> i32 {
+            let mut scripts = self.scripts.lock().unwrap();
+            scripts.push(fs::read_to_string(path).unwrap());
+            scripts.len() as i32 - 1
+        }
+
+        #[zbus(name = "unloadScript")]
+        fn unload_script(&self, plugin: String) -> bool {
+            self.unloaded.lock().unwrap().push(plugin);
+            true
+        }
+    }
+
+    struct MockScript {
+        id: usize,
+        scripts: Arc<Mutex<Vec<String>>>,
+        respond: Respond,
+    }
+
+    #[zbus::interface(name = "org.kde.kwin.Script")]
+    impl MockScript {
+        #[zbus(name = "run")]
+        fn run(&self, #[zbus(connection)] conn: &zbus::Connection) {
+            let source = self.scripts.lock().unwrap()[self.id].clone();
+            let token = source
+                .split("\"Reply\", \"")
+                .nth(1)
+                .and_then(|rest| rest.split('"').next())
+                .map(str::to_string);
+            let data = (self.respond)(&source);
+
+            // Call back the way a script would, once this call has returned. A
+            // watch script has no token; it reports a change straight away.
+            let conn = Connection::from(conn.clone());
+            std::thread::spawn(move || {
+                match token {
+                    Some(token) => conn.call_method(
+                        None::<&str>,
+                        BRIDGE_PATH,
+                        Some("org.nicotine.KWinBridge"),
+                        "Reply",
+                        &(token, data),
+                    ),
+                    None => conn.call_method(
+                        None::<&str>,
+                        BRIDGE_PATH,
+                        Some("org.nicotine.KWinBridge"),
+                        "Changed",
+                        &(),
+                    ),
+                }
+                .unwrap();
+            });
+        }
+    }
+
+    fn mock_kwin(name: &str, respond: impl Fn(&str) -> String + Send + Sync + 'static) -> MockKWin {
+        let script_dir = std::env::temp_dir().join(format!(
+            "nicotine-test-kwin-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&script_dir);
+        fs::create_dir(&script_dir).unwrap();
+
+        let scripts = Arc::new(Mutex::new(Vec::new()));
+        let unloaded = Arc::new(Mutex::new(Vec::new()));
+        let respond: Respond = Arc::new(respond);
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+
+        let server = {
+            let scripts = Arc::clone(&scripts);
+            let unloaded = Arc::clone(&unloaded);
+            std::thread::spawn(move || -> zbus::Result<Connection> {
+                let mut builder = Builder::async_io_unix_stream(server_stream)
+                    .server(zbus::Guid::generate())?
+                    .p2p()
+                    .serve_at(
+                        "/Scripting",
+                        MockScripting {
+                            scripts: Arc::clone(&scripts),
+                            unloaded,
+                        },
+                    )?;
+                for id in 0..8 {
+                    builder = builder.serve_at(
+                        format!("/Scripting/Script{}", id),
+                        MockScript {
+                            id,
+                            scripts: Arc::clone(&scripts),
+                            respond: Arc::clone(&respond),
+                        },
+                    )?;
+                }
+                builder.build()
+            })
+        };
+
+        let client = Builder::async_io_unix_stream(client_stream)
+            .p2p()
+            .build()
+            .unwrap();
+        let server = server.join().unwrap().unwrap();
+
+        MockKWin {
+            scripting: KWinScripting::new(client, None, script_dir).unwrap(),
+            scripts,
+            unloaded,
+            _server: server,
+        }
+    }
+
+    #[test]
+    fn test_script_reply_round_trip() {
+        let kwin = mock_kwin("round-trip", |_| "[1, 2, 3]".to_string());
+
+        let reply: Vec<u32> = kwin.scripting.run("reply([1, 2, 3]);").unwrap();
+        assert_eq!(reply, vec![1, 2, 3]);
+
+        let scripts = kwin.scripts.lock().unwrap();
+        assert!(scripts[0].starts_with("// Generated by nicotine"));
+        assert!(scripts[0].ends_with("reply([1, 2, 3]);"));
+        assert!(!scripts[0].contains("__TOKEN__"));
+        assert_eq!(*kwin.unloaded.lock().unwrap(), vec!["nicotine-1"]);
+    }
+
+    #[test]
+    fn test_each_script_gets_its_own_token() {
+        let kwin = mock_kwin("tokens", |source| {
+            if source.contains("first") {
+                "\"first\"".to_string()
+            } else {
+                "\"second\"".to_string()
+            }
+        });
+
+        let first: String = kwin.scripting.run("reply('first');").unwrap();
+        let second: String = kwin.scripting.run("reply('second');").unwrap();
+        assert_eq!((first.as_str(), second.as_str()), ("first", "second"));
+
+        // Script files are cleaned up after each run
+        assert_eq!(fs::read_dir(&kwin.scripting.script_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_watch_script_reports_changes() {
+        let kwin = mock_kwin("watch", |_| String::new());
+        let (notify, changes) = mpsc::channel();
+
+        kwin.scripting.watch("changed();", notify).unwrap();
+        changes.recv_timeout(REPLY_TIMEOUT).unwrap();
+
+        let scripts = kwin.scripts.lock().unwrap();
+        assert!(scripts[0].contains("\"Changed\""));
+        assert!(!scripts[0].contains("__SERVICE__"));
+        // Any watcher left behind by an earlier daemon is unloaded first
+        assert_eq!(*kwin.unloaded.lock().unwrap(), vec![WATCH_PLUGIN]);
+    }
+
+    #[test]
+    fn test_manager_lists_and_activates_by_uuid() {
+        let kwin = mock_kwin("manager", |source| {
+            if source.contains("normalWindow") {
+                r#"[{"id":"{1111}","caption":"EVE - Alpha"},{"id":"{2222}","caption":"Konsole"}]"#
+                    .to_string()
+            } else {
+                "true".to_string()
+            }
+        });
+        let scripts = Arc::clone(&kwin.scripts);
+        let manager = KWinManager::with_scripting(kwin.scripting);
+
+        let windows = manager.get_eve_windows().unwrap();
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows[0].title, "Alpha");
+
+        manager.activate_window(windows[0].id).unwrap();
+        let scripts = scripts.lock().unwrap();
+        assert!(scripts[1].contains("findWindow(\"{1111}\")"));
+        assert!(scripts[1].contains("activate(w);"));
+    }
+}
@@ -0,0 +1,518 @@
+use crate::config::{OverlayAnchor, OverlayConfig};
+use crate::overlay::OverlayApp;
+use anyhow::{Context, Result};
+use eframe::egui;
+use glutin::api::egl::{context::PossiblyCurrentContext, display::Display, surface::Surface};
+use glutin::config::ConfigTemplateBuilder;
+use glutin::context::{ContextApi, ContextAttributesBuilder, NotCurrentGlContext};
+use glutin::display::GlDisplay;
+use glutin::surface::{GlSurface, SurfaceAttributesBuilder, SwapInterval, WindowSurface};
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use raw_window_handle::{
+    RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle,
+};
+use smithay_client_toolkit::compositor::{CompositorHandler, CompositorState};
+use smithay_client_toolkit::output::{OutputHandler, OutputState};
+use smithay_client_toolkit::registry::{ProvidesRegistryState, RegistryState};
+use smithay_client_toolkit::seat::pointer::{PointerEvent, PointerEventKind, PointerHandler};
+use smithay_client_toolkit::seat::{Capability, SeatHandler, SeatState};
+use smithay_client_toolkit::shell::wlr_layer::{
+    Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
+    LayerSurfaceConfigure,
+};
+use smithay_client_toolkit::shell::WaylandSurface;
+use smithay_client_toolkit::{
+    delegate_compositor, delegate_layer, delegate_output, delegate_pointer, delegate_registry,
+    delegate_seat, registry_handlers,
+};
+use std::ffi::c_void;
+use std::num::NonZeroU32;
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::time::Instant;
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_output, wl_pointer, wl_registry, wl_seat, wl_surface};
+use wayland_client::{Connection, Dispatch, Proxy, QueueHandle};
+
+/// How long to wait for input before redrawing anyway to pick up state changes
+const REFRESH_INTERVAL_MS: u16 = 100;
+
+/// Linux input button codes, as sent in `wl_pointer.button`
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+
+/// Whether we're on Wayland and the compositor offers `zwlr_layer_shell_v1`
+pub fn is_supported() -> bool {
+    let Ok(conn) = Connection::connect_to_env() else {
+        return false;
+    };
+    let Ok((globals, _queue)) = registry_queue_init::<RegistryProbe>(&conn) else {
+        return false;
+    };
+    globals
+        .contents()
+        .with_list(|list| list.iter().any(|g| g.interface == "zwlr_layer_shell_v1"))
+}
+
+struct RegistryProbe;
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for RegistryProbe {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+fn anchor_edges(anchor: OverlayAnchor) -> Anchor {
+    match anchor {
+        OverlayAnchor::TopLeft => Anchor::TOP | Anchor::LEFT,
+        OverlayAnchor::Top => Anchor::TOP,
+        OverlayAnchor::TopRight => Anchor::TOP | Anchor::RIGHT,
+        OverlayAnchor::Left => Anchor::LEFT,
+        OverlayAnchor::Right => Anchor::RIGHT,
+        OverlayAnchor::BottomLeft => Anchor::BOTTOM | Anchor::LEFT,
+        OverlayAnchor::Bottom => Anchor::BOTTOM,
+        OverlayAnchor::BottomRight => Anchor::BOTTOM | Anchor::RIGHT,
+    }
+}
+
+/// Run the overlay as a layer-shell surface on the overlay layer, so it stays
+/// above EVE even when the client is fullscreen. Blocks until the compositor
+/// closes the surface.
+pub fn run(ctx: egui::Context, app: OverlayApp, config: &OverlayConfig) -> Result<()> {
+    let conn = Connection::connect_to_env().context("Failed to connect to Wayland display")?;
+    let (globals, mut queue) =
+        registry_queue_init(&conn).context("Failed to read Wayland globals")?;
+    let qh = queue.handle();
+
+    let compositor = CompositorState::bind(&globals, &qh).context("wl_compositor not available")?;
+    let layer_shell = LayerShell::bind(&globals, &qh).context("Layer shell not available")?;
+
+    let surface = compositor.create_surface(&qh);
+    let layer =
+        layer_shell.create_layer_surface(&qh, surface, Layer::Overlay, Some("nicotine"), None);
+    let margin = config.margin;
+    layer.set_anchor(anchor_edges(config.anchor));
+    layer.set_margin(margin, margin, margin, margin);
+    layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+    let size = app.size();
+    let size = (size.x.round() as u32, size.y.round() as u32);
+    layer.set_size(size.0, size.1);
+    // Initial commit without a buffer; drawing starts at the first configure
+    layer.commit();
+
+    let mut overlay = LayerOverlay {
+        registry_state: RegistryState::new(&globals),
+        seat_state: SeatState::new(&globals, &qh),
+        output_state: OutputState::new(&globals, &qh),
+        conn: conn.clone(),
+        layer,
+        pointer: None,
+        egl: None,
+        size,
+        requested_size: size,
+        scale: 1,
+        exit: false,
+        events: Vec::new(),
+        pointer_pos: egui::Pos2::ZERO,
+        start: Instant::now(),
+        ctx,
+        app,
+    };
+
+    while !overlay.exit {
+        queue.dispatch_pending(&mut overlay)?;
+        overlay.draw()?;
+        queue.flush()?;
+
+        // Sleep until there's input or it's time to pick up state changes
+        if let Some(guard) = queue.prepare_read() {
+            let mut fds = [PollFd::new(guard.connection_fd(), PollFlags::POLLIN)];
+            if poll(&mut fds, PollTimeout::from(REFRESH_INTERVAL_MS))? > 0 {
+                guard.read()?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// EGL state, created at the first configure once the surface has a size
+struct Egl {
+    surface: Surface<WindowSurface>,
+    context: PossiblyCurrentContext,
+    painter: egui_glow::Painter,
+}
+
+impl Egl {
+    fn new(
+        conn: &Connection,
+        wl_surface: &wl_surface::WlSurface,
+        size: (u32, u32),
+    ) -> Result<Self> {
+        let display_ptr = NonNull::new(conn.backend().display_ptr() as *mut c_void)
+            .context("No Wayland display pointer")?;
+        let surface_ptr = NonNull::new(wl_surface.id().as_ptr() as *mut c_void)
+            .context("No Wayland surface pointer")?;
+        let display_handle = RawDisplayHandle::Wayland(WaylandDisplayHandle::new(display_ptr));
+        let window_handle = RawWindowHandle::Wayland(WaylandWindowHandle::new(surface_ptr));
+
+        // SAFETY: both handles point at live objects owned by `conn` and the
+        // layer surface; `LayerOverlay` declares `egl` first so it is dropped
+        // before either of them
+        let display = unsafe { Display::new(display_handle) }.context("Failed to open EGL")?;
+        let template = ConfigTemplateBuilder::new().with_alpha_size(8).build();
+        let config = unsafe { display.find_configs(template) }?
+            .next()
+            .context("No suitable EGL config")?;
+
+        // Prefer desktop GL, fall back to GLES
+        let context = unsafe {
+            display
+                .create_context(
+                    &config,
+                    &ContextAttributesBuilder::new().build(Some(window_handle)),
+                )
+                .or_else(|_| {
+                    display.create_context(
+                        &config,
+                        &ContextAttributesBuilder::new()
+                            .with_context_api(ContextApi::Gles(None))
+                            .build(Some(window_handle)),
+                    )
+                })
+        }
+        .context("Failed to create GL context")?;
+
+        let attributes = SurfaceAttributesBuilder::<WindowSurface>::new().build(
+            window_handle,
+            non_zero(size.0),
+            non_zero(size.1),
+        );
+        let surface = unsafe { display.create_window_surface(&config, &attributes) }
+            .context("Failed to create EGL surface")?;
+        let context = context.make_current(&surface)?;
+
+        // Don't block in swap_buffers waiting for frame callbacks; we pace ourselves
+        let _ = surface.set_swap_interval(&context, SwapInterval::DontWait);
+
+        let gl = unsafe {
+            glow::Context::from_loader_function_cstr(|name| display.get_proc_address(name))
+        };
+        let painter = egui_glow::Painter::new(Arc::new(gl), "", None, false)
+            .map_err(|e| anyhow::anyhow!("Failed to create painter: {}", e))?;
+
+        Ok(Self {
+            surface,
+            context,
+            painter,
+        })
+    }
+}
+
+impl Drop for Egl {
+    fn drop(&mut self) {
+        // The context is still current; without this egui_glow logs that its
+        // GL resources leak
+        self.painter.destroy();
+    }
+}
+
+fn non_zero(value: u32) -> NonZeroU32 {
+    NonZeroU32::new(value.max(1)).unwrap()
+}
+
+struct LayerOverlay {
+    /// Must stay the first field: fields drop in order, and EGL has to go
+    /// before the surface and connection it renders to
+    egl: Option<Egl>,
+    registry_state: RegistryState,
+    seat_state: SeatState,
+    output_state: OutputState,
+    conn: Connection,
+    layer: LayerSurface,
+    pointer: Option<wl_pointer::WlPointer>,
+    /// Size in surface-local (logical) pixels, as last configured
+    size: (u32, u32),
+    /// Size last asked of the compositor
+    requested_size: (u32, u32),
+    scale: i32,
+    exit: bool,
+    /// Input collected since the last frame
+    events: Vec<egui::Event>,
+    pointer_pos: egui::Pos2,
+    start: Instant,
+    ctx: egui::Context,
+    app: OverlayApp,
+}
+
+impl LayerOverlay {
+    fn buffer_size(&self) -> (u32, u32) {
+        let scale = self.scale.max(1) as u32;
+        (self.size.0 * scale, self.size.1 * scale)
+    }
+
+    fn draw(&mut self) -> Result<()> {
+        // Follow the client count like the eframe overlay does
+        let size = self.app.size();
+        let size = (size.x.round() as u32, size.y.round() as u32);
+        if size != self.requested_size {
+            self.requested_size = size;
+            self.layer.set_size(size.0, size.1);
+            self.layer.commit();
+        }
+
+        let buffer_size = self.buffer_size();
+        let Some(egl) = &mut self.egl else {
+            return Ok(());
+        };
+
+        let mut input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(self.size.0 as f32, self.size.1 as f32),
+            )),
+            time: Some(self.start.elapsed().as_secs_f64()),
+            events: std::mem::take(&mut self.events),
+            ..Default::default()
+        };
+        input
+            .viewports
+            .entry(egui::ViewportId::ROOT)
+            .or_default()
+            .native_pixels_per_point = Some(self.scale as f32);
+
+        let app = &self.app;
+        let output = self.ctx.run(input, |ctx| app.show(ctx));
+        let primitives = self.ctx.tessellate(output.shapes, output.pixels_per_point);
+
+        let size = [buffer_size.0, buffer_size.1];
+        egl.painter.clear(size, [0.0; 4]);
+        egl.painter.paint_and_update_textures(
+            size,
+            output.pixels_per_point,
+            &primitives,
+            &output.textures_delta,
+        );
+        egl.surface
+            .swap_buffers(&egl.context)
+            .context("Failed to present overlay")
+    }
+
+    fn resize_surface(&mut self) {
+        let (width, height) = self.buffer_size();
+        if let Some(egl) = &self.egl {
+            egl.surface
+                .resize(&egl.context, non_zero(width), non_zero(height));
+        }
+    }
+}
+
+impl CompositorHandler for LayerOverlay {
+    fn scale_factor_changed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        surface: &wl_surface::WlSurface,
+        new_factor: i32,
+    ) {
+        self.scale = new_factor;
+        surface.set_buffer_scale(new_factor);
+        self.resize_surface();
+    }
+
+    fn transform_changed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        _: wl_output::Transform,
+    ) {
+    }
+
+    fn frame(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: u32) {}
+
+    fn surface_enter(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        _: &wl_output::WlOutput,
+    ) {
+    }
+
+    fn surface_leave(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        _: &wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl OutputHandler for LayerOverlay {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+
+    fn update_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+
+    fn output_destroyed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+}
+
+impl LayerShellHandler for LayerOverlay {
+    fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &LayerSurface) {
+        self.exit = true;
+    }
+
+    fn configure(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        layer: &LayerSurface,
+        configure: LayerSurfaceConfigure,
+        _: u32,
+    ) {
+        // Zero means we get to pick, i.e. the size we asked for
+        if configure.new_size.0 > 0 && configure.new_size.1 > 0 {
+            self.size = configure.new_size;
+        }
+
+        if self.egl.is_none() {
+            match Egl::new(&self.conn, layer.wl_surface(), self.buffer_size()) {
+                Ok(egl) => self.egl = Some(egl),
+                Err(e) => {
+                    eprintln!("Overlay error: {:#}", e);
+                    self.exit = true;
+                }
+            }
+        } else {
+            self.resize_surface();
+        }
+    }
+}
+
+impl SeatHandler for LayerOverlay {
+    fn seat_state(&mut self) -> &mut SeatState {
+        &mut self.seat_state
+    }
+
+    fn new_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+
+    fn new_capability(
+        &mut self,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+        seat: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Pointer && self.pointer.is_none() {
+            self.pointer = self.seat_state.get_pointer(qh, &seat).ok();
+        }
+    }
+
+    fn remove_capability(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        if capability == Capability::Pointer {
+            if let Some(pointer) = self.pointer.take() {
+                pointer.release();
+            }
+        }
+    }
+
+    fn remove_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+}
+
+impl PointerHandler for LayerOverlay {
+    fn pointer_frame(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_pointer::WlPointer,
+        events: &[PointerEvent],
+    ) {
+        for event in events {
+            if &event.surface != self.layer.wl_surface() {
+                continue;
+            }
+            if let Some(event) = translate_pointer_event(event, &mut self.pointer_pos) {
+                self.events.push(event);
+            }
+        }
+    }
+}
+
+/// Turn a Wayland pointer event into the egui event it corresponds to
+fn translate_pointer_event(event: &PointerEvent, pos: &mut egui::Pos2) -> Option<egui::Event> {
+    let (x, y) = event.position;
+    *pos = egui::pos2(x as f32, y as f32);
+
+    match event.kind {
+        PointerEventKind::Enter { .. } | PointerEventKind::Motion { .. } => {
+            Some(egui::Event::PointerMoved(*pos))
+        }
+        PointerEventKind::Leave { .. } => Some(egui::Event::PointerGone),
+        PointerEventKind::Press { button, .. } | PointerEventKind::Release { button, .. } => {
+            let button = match button {
+                BTN_LEFT => egui::PointerButton::Primary,
+                BTN_RIGHT => egui::PointerButton::Secondary,
+                BTN_MIDDLE => egui::PointerButton::Middle,
+                _ => return None,
+            };
+            Some(egui::Event::PointerButton {
+                pos: *pos,
+                button,
+                pressed: matches!(event.kind, PointerEventKind::Press { .. }),
+                modifiers: egui::Modifiers::default(),
+            })
+        }
+        PointerEventKind::Axis { .. } => None,
+    }
+}
+
+impl ProvidesRegistryState for LayerOverlay {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry_state
+    }
+
+    registry_handlers![OutputState, SeatState];
+}
+
+delegate_compositor!(LayerOverlay);
+delegate_output!(LayerOverlay);
+delegate_layer!(LayerOverlay);
+delegate_seat!(LayerOverlay);
+delegate_pointer!(LayerOverlay);
+delegate_registry!(LayerOverlay);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchor_edges() {
+        assert_eq!(
+            anchor_edges(OverlayAnchor::TopLeft),
+            Anchor::TOP | Anchor::LEFT
+        );
+        assert_eq!(anchor_edges(OverlayAnchor::Right), Anchor::RIGHT);
+        assert_eq!(
+            anchor_edges(OverlayAnchor::BottomRight),
+            Anchor::BOTTOM | Anchor::RIGHT
+        );
+    }
+}
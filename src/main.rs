@@ -1,40 +1,61 @@
 mod config;
+mod config_watcher;
 mod cycle_state;
 mod daemon;
+mod events;
+mod gnome_shell;
+mod i3_manager;
+mod i3ipc;
+mod ipc;
+mod kwin_scripting;
+mod layer_overlay;
 mod mouse_listener;
 mod overlay;
+mod preview;
+mod runtime;
+// Only fixtures several modules' tests use; the rest stay in their own module
+#[cfg(test)]
+mod test_support;
 mod version_check;
 mod wayland_backends;
 mod window_manager;
+mod wlr_toplevel;
 mod x11_manager;
 
 use anyhow::Result;
 use config::Config;
 use cycle_state::CycleState;
-use daemon::Daemon;
+use daemon::{Command, Daemon};
 use daemonize::Daemonize;
+use i3_manager::I3Manager;
+use ipc::StatusReport;
 #[allow(deprecated)]
 use nix::fcntl::{flock, FlockArg};
 use overlay::run_overlay;
 use std::env;
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
 use std::os::unix::fs::OpenOptionsExt;
 use std::os::unix::io::AsRawFd;
 use std::sync::{Arc, Mutex};
-use wayland_backends::{HyprlandManager, KWinManager, SwayManager};
+use wayland_backends::{GnomeManager, HyprlandManager, KWinManager, NiriManager, SwayManager};
 use window_manager::{
-    detect_display_server, detect_wayland_compositor, DisplayServer, WaylandCompositor,
+    detect_display_server, detect_wayland_compositor, Backend, DisplayServer, WaylandCompositor,
     WindowManager,
 };
-use x11_manager::X11Manager;
+use wlr_toplevel::WlrToplevelManager;
+use x11_manager::{i3_socket_path, X11Manager};
 
-fn create_window_manager() -> Result<Arc<dyn WindowManager>> {
-    let display_server = detect_display_server();
-
-    match display_server {
+/// Pick a backend from the session: display server, then compositor
+fn detect_backend() -> Backend {
+    match detect_display_server() {
         DisplayServer::X11 => {
             println!("Detected X11 display server");
-            Ok(Arc::new(X11Manager::new()?))
+
+            if i3_socket_path().is_some() {
+                Backend::I3
+            } else {
+                Backend::X11
+            }
         }
         DisplayServer::Wayland => {
             let compositor = detect_wayland_compositor();
@@ -44,41 +65,151 @@ fn create_window_manager() -> Result<Arc<dyn WindowManager>> {
             );
 
             match compositor {
-                WaylandCompositor::Kde => {
-                    println!("Using KDE/KWin backend");
-                    Ok(Arc::new(KWinManager::new()?))
-                }
-                WaylandCompositor::Sway => {
-                    println!("Using Sway backend");
-                    Ok(Arc::new(SwayManager::new()?))
-                }
-                WaylandCompositor::Hyprland => {
-                    println!("Using Hyprland backend");
-                    Ok(Arc::new(HyprlandManager::new()?))
-                }
-                WaylandCompositor::Gnome => {
-                    anyhow::bail!("GNOME Shell is not yet supported due to restrictive window management APIs")
-                }
-                WaylandCompositor::Other => {
-                    anyhow::bail!(
-                        "Unknown Wayland compositor. Supported: KDE Plasma, Sway, Hyprland"
-                    )
-                }
+                WaylandCompositor::Kde => Backend::Kde,
+                WaylandCompositor::Sway => Backend::Sway,
+                WaylandCompositor::Hyprland => Backend::Hyprland,
+                WaylandCompositor::Niri => Backend::Niri,
+                WaylandCompositor::Gnome => Backend::Gnome,
+                WaylandCompositor::Other => Backend::Wlr,
             }
         }
     }
 }
 
+fn create_window_manager(config: &Config) -> Result<Arc<dyn WindowManager>> {
+    let backend = match config.backend {
+        Backend::Auto => detect_backend(),
+        backend => {
+            println!("Using backend '{}' from config.toml", backend);
+            backend
+        }
+    };
+
+    match backend {
+        Backend::Auto => unreachable!("detect_backend always picks one"),
+        Backend::X11 => Ok(Arc::new(X11Manager::new()?)),
+        Backend::I3 => {
+            println!("Using i3 backend");
+            Ok(Arc::new(I3Manager::new()?))
+        }
+        Backend::Kde => {
+            println!("Using KDE/KWin backend");
+            Ok(Arc::new(KWinManager::new()?))
+        }
+        Backend::Sway => {
+            println!("Using Sway backend");
+            Ok(Arc::new(SwayManager::new()?))
+        }
+        Backend::Hyprland => {
+            println!("Using Hyprland backend");
+            Ok(Arc::new(HyprlandManager::new()?))
+        }
+        Backend::Niri => {
+            println!("Using niri backend");
+            Ok(Arc::new(NiriManager::new()?))
+        }
+        Backend::Gnome => {
+            println!("Using GNOME Shell backend");
+            Ok(Arc::new(GnomeManager::new()?))
+        }
+        Backend::Wlr => {
+            let manager = WlrToplevelManager::new().map_err(|e| {
+                anyhow::anyhow!(
+                    "No supported window manager found ({}). Supported: KDE Plasma, GNOME, \
+                     Sway, Hyprland, niri, i3 and compositors with \
+                     wlr-foreign-toplevel-management; set `backend` in config.toml to force one",
+                    e
+                )
+            })?;
+            println!("Using wlr-foreign-toplevel backend");
+            Ok(Arc::new(manager))
+        }
+    }
+}
+
+/// Take the direct-mode cycle lock so overlapping hotkey presses don't race.
+/// Returns None if another cycle is already running (or the lock can't be opened).
+/// The lock is released when the returned file is dropped.
+fn acquire_cycle_lock() -> Option<File> {
+    runtime::ensure_runtime_dir().ok()?;
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(runtime::lock_path())
+        .ok()?;
+
+    // Try to lock (non-blocking)
+    #[allow(deprecated)]
+    flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock).ok()?;
+
+    Some(file)
+}
+
+fn print_status_header(report: &StatusReport) {
+    println!("Daemon running (pid {})", report.pid);
+    println!(
+        "Backend: {} ({})",
+        report.backend,
+        report.capabilities.names().join(", ")
+    );
+
+    match report.clients.iter().find(|c| c.active) {
+        Some(active) => println!(
+            "{} clients, active: {}",
+            report.clients.len(),
+            active.character
+        ),
+        None => println!("{} clients", report.clients.len()),
+    }
+    println!();
+}
+
+fn print_client_table(report: &StatusReport) {
+    if report.clients.is_empty() {
+        println!("No EVE clients found");
+        return;
+    }
+
+    println!("  {:>3}  {:>4}  {:>15}  CHARACTER", "#", "SLOT", "WINDOW");
+    for client in &report.clients {
+        let marker = if client.active { "▸" } else { " " };
+        let slot = client
+            .slot
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "{} {:>3}  {:>4}  {:>15}  {}",
+            marker, client.index, slot, client.window_id, client.character
+        );
+    }
+}
+
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
     let command = args.get(1).map(|s| s.as_str()).unwrap_or("");
 
-    let config = Config::load()?;
-    let wm = create_window_manager()?;
+    // Runs before the extension exists, so it can't need a window manager
+    if command == "install-gnome-extension" {
+        return gnome_shell::install_extension();
+    }
+
+    // Only commands that drive windows themselves need a window manager, so
+    // the daemon queries still work when the local backend can't start
+    let setup = || -> Result<(Config, Arc<dyn WindowManager>)> {
+        let config = Config::load()?;
+        let wm = create_window_manager(&config)?;
+        Ok((config, wm))
+    };
 
     match command {
         "start" => {
             println!("Starting Nicotine 🚬");
+            let (config, wm) = setup()?;
+            // Checked before daemonizing so a broken characters.txt is reported here
+            let character_order = Config::load_characters()?;
 
             // Check for updates (non-blocking, silent on errors)
             if let Ok(Some((new_version, url))) = version_check::check_for_updates() {
@@ -92,28 +223,20 @@ fn main() -> Result<()> {
                 Ok(_) => {
                     // We're now in the daemon process
                     // Start daemon in background thread
-                    let wm_daemon = Arc::clone(&wm);
-                    let config_daemon = config.clone();
+                    let daemon = Daemon::new(Arc::clone(&wm), config.clone(), character_order);
+                    // The overlay shares the daemon's state and command queue directly
+                    let state = daemon.state();
+                    let commands = daemon.commands();
+                    let daemon_config = daemon.config();
                     let daemon_thread = std::thread::spawn(move || {
-                        let mut daemon = Daemon::new(wm_daemon, config_daemon);
                         if let Err(e) = daemon.run() {
                             eprintln!("Daemon error: {}", e);
                         }
                     });
 
-                    // Wait a bit for daemon to initialize
-                    std::thread::sleep(std::time::Duration::from_millis(100));
-
                     if config.show_overlay {
                         // Run overlay in main thread
-                        let state = Arc::new(Mutex::new(CycleState::new()));
-                        if let Ok(windows) = wm.get_eve_windows() {
-                            state.lock().unwrap().update_windows(windows);
-                        }
-
-                        if let Err(e) =
-                            run_overlay(wm, state, config.overlay_x, config.overlay_y, config)
-                        {
+                        if let Err(e) = run_overlay(wm, state, Some(commands), daemon_config) {
                             eprintln!("Overlay error: {}", e);
                             std::process::exit(1);
                         }
@@ -132,26 +255,44 @@ fn main() -> Result<()> {
 
         "daemon" => {
             println!("Starting EVE Multibox daemon...");
-            let mut daemon = Daemon::new(wm, config);
+            let (config, wm) = setup()?;
+            let character_order = Config::load_characters()?;
+            let daemon = Daemon::new(wm, config, character_order);
             daemon.run()?;
         }
 
         "overlay" => {
             println!("Starting EVE Multibox Overlay...");
+            let (config, wm) = setup()?;
             let state = Arc::new(Mutex::new(CycleState::new()));
+            let config = Arc::new(Mutex::new(config));
 
-            // Initialize windows
-            if let Ok(windows) = wm.get_eve_windows() {
-                state.lock().unwrap().update_windows(windows);
-            }
+            // Follow the daemon if one is running, otherwise poll on our own
+            overlay::spawn_state_follower(Arc::clone(&wm), Arc::clone(&state), Arc::clone(&config));
 
-            if let Err(e) = run_overlay(wm, state, config.overlay_x, config.overlay_y, config) {
+            if let Err(e) = run_overlay(wm, state, None, config) {
                 eprintln!("Overlay error: {}", e);
                 std::process::exit(1);
             }
         }
 
         "stack" => {
+            // Try daemon first so subscribers see the stack
+            if let Ok(response) = daemon::send_command(Command::Stack) {
+                response.into_result()?;
+                println!("✓ Stacked windows");
+                return Ok(());
+            }
+            let (config, wm) = setup()?;
+
+            if !wm.capabilities().resize {
+                anyhow::bail!(
+                    "The {} backend can't move or resize windows; use your compositor's \
+                     window rules to place EVE clients",
+                    wm.backend()
+                );
+            }
+
             println!("Stacking EVE windows...");
             let windows = wm.get_eve_windows()?;
 
@@ -171,30 +312,17 @@ fn main() -> Result<()> {
 
         "cycle-forward" | "forward" | "f" => {
             // Try daemon first
-            if daemon::send_command("forward").is_ok() {
-                return Ok(());
+            if let Ok(response) = daemon::send_command(Command::Forward) {
+                return response.into_result();
             }
 
             // Fallback to direct mode
+            let (config, wm) = setup()?;
 
             // Try to acquire lock, exit immediately if already running
-            let lock_file = "/tmp/nicotine-cycle.lock";
-            let file = match OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .mode(0o644)
-                .open(lock_file)
-            {
-                Ok(f) => f,
-                Err(_) => return Ok(()), // Can't get lock, skip
-            };
-
-            // Try to lock (non-blocking)
-            #[allow(deprecated)]
-            if flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock).is_err() {
+            let Some(_lock) = acquire_cycle_lock() else {
                 return Ok(()); // Already running, skip this cycle
-            }
+            };
 
             let mut state = CycleState::new();
             let windows = wm.get_eve_windows()?;
@@ -217,30 +345,17 @@ fn main() -> Result<()> {
 
         "cycle-backward" | "backward" | "b" => {
             // Try daemon first
-            if daemon::send_command("backward").is_ok() {
-                return Ok(());
+            if let Ok(response) = daemon::send_command(Command::Backward) {
+                return response.into_result();
             }
 
             // Fallback to direct mode
+            let (config, wm) = setup()?;
 
             // Try to acquire lock, exit immediately if already running
-            let lock_file = "/tmp/nicotine-cycle.lock";
-            let file = match OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .mode(0o644)
-                .open(lock_file)
-            {
-                Ok(f) => f,
-                Err(_) => return Ok(()), // Can't get lock, skip
-            };
-
-            // Try to lock (non-blocking)
-            #[allow(deprecated)]
-            if flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock).is_err() {
+            let Some(_lock) = acquire_cycle_lock() else {
                 return Ok(()); // Already running, skip this cycle
-            }
+            };
 
             let mut state = CycleState::new();
             let windows = wm.get_eve_windows()?;
@@ -264,17 +379,50 @@ fn main() -> Result<()> {
         "stop" => {
             println!("Stopping Nicotine...");
 
-            // Kill all nicotine processes
-            let _ = std::process::Command::new("pkill")
-                .arg("-9")
-                .arg("nicotine")
-                .output();
+            // Ask the daemon to clean up and exit; only signal it if that fails
+            let stopped = match daemon::send_command(Command::Quit) {
+                Ok(response) => {
+                    response.into_result()?;
+                    true
+                }
+                Err(_) => daemon::kill_recorded_daemon()?,
+            };
 
-            println!("✓ Nicotine stopped");
+            if stopped {
+                println!("✓ Nicotine stopped");
+            } else {
+                println!("Nicotine is not running");
+            }
+
+            // Clean up anything a killed daemon left behind
+            let _ = std::fs::remove_file(runtime::socket_path());
+            let _ = std::fs::remove_file(runtime::pid_path());
+            let _ = std::fs::remove_file(runtime::lock_path());
+        }
+
+        "status" | "list" => {
+            let report = daemon::send_command(Command::Status)?.into_status()?;
+
+            if args.iter().any(|a| a == "--json") {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                if command == "status" {
+                    print_status_header(&report);
+                }
+                print_client_table(&report);
+            }
+        }
 
-            // Clean up socket and lock files
-            let _ = std::fs::remove_file("/tmp/nicotine.sock");
-            let _ = std::fs::remove_file("/tmp/nicotine-cycle.lock");
+        "reload" => {
+            daemon::send_command(Command::Reload)?.into_result()?;
+            println!("✓ Configuration reloaded");
+        }
+
+        "subscribe" => {
+            // Print each event as a JSON line for status bars and scripts
+            for event in daemon::subscribe()? {
+                println!("{}", serde_json::to_string(&event?)?);
+            }
         }
 
         "init-config" => {
@@ -293,27 +441,15 @@ fn main() -> Result<()> {
 
             if let Some(target) = target {
                 // Try daemon first
-                if daemon::send_command(&format!("switch:{}", target)).is_ok() {
-                    return Ok(());
+                if let Ok(response) = daemon::send_command(Command::Switch(target)) {
+                    return response.into_result();
                 }
 
                 // Fallback to direct mode
-                let lock_file = "/tmp/nicotine-cycle.lock";
-                let file = match OpenOptions::new()
-                    .write(true)
-                    .create(true)
-                    .truncate(true)
-                    .mode(0o644)
-                    .open(lock_file)
-                {
-                    Ok(f) => f,
-                    Err(_) => return Ok(()),
-                };
-
-                #[allow(deprecated)]
-                if flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock).is_err() {
+                let (config, wm) = setup()?;
+                let Some(_lock) = acquire_cycle_lock() else {
                     return Ok(());
-                }
+                };
 
                 let mut state = CycleState::new();
                 let windows = wm.get_eve_windows()?;
@@ -328,7 +464,7 @@ fn main() -> Result<()> {
                     state.sync_with_active(active);
                 }
 
-                let character_order = Config::load_characters();
+                let character_order = Config::load_characters()?;
                 state.switch_to(
                     target,
                     &*wm,
@@ -344,13 +480,18 @@ fn main() -> Result<()> {
                 println!();
                 println!("Usage:");
                 println!("  nicotine start         - Start everything (daemon + overlay)");
-                println!("  nicotine stop          - Stop all Nicotine processes");
+                println!("  nicotine stop          - Stop the Nicotine daemon");
                 println!("  nicotine stack         - Stack all EVE windows");
                 println!("  nicotine forward       - Cycle forward");
                 println!("  nicotine backward      - Cycle backward");
                 println!("  nicotine switch N      - Switch to client N (targeted cycling)");
                 println!("  nicotine N             - Shorthand for switch N");
+                println!("  nicotine status        - Show daemon status and clients");
+                println!("  nicotine list          - List clients (add --json for JSON output)");
+                println!("  nicotine reload        - Reload config.toml and characters.txt");
+                println!("  nicotine subscribe     - Stream daemon events as JSON lines");
                 println!("  nicotine init-config   - Create default config.toml");
+                println!("  nicotine install-gnome-extension - Install the GNOME Shell extension");
                 println!();
                 println!("Advanced:");
                 println!("  nicotine daemon        - Start daemon only");
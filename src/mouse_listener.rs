@@ -1,15 +1,33 @@
 use crate::config::Config;
-use crate::cycle_state::CycleState;
-use crate::window_manager::WindowManager;
+use crate::daemon::{Command, CommandSender};
 use anyhow::{Context, Result};
 use evdev::{Device, InputEventKind, Key};
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use std::os::fd::{AsRawFd, BorrowedFd};
 use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// How often a listener waiting for input checks whether it has been stopped
+const STOP_CHECK_INTERVAL_MS: u16 = 500;
 
 pub struct MouseListener {
     config: Config,
 }
 
+/// Handle to a running listener thread, used to stop it when its settings change
+pub struct MouseListenerHandle {
+    stop: Arc<AtomicBool>,
+}
+
+impl MouseListenerHandle {
+    /// Ask the listener thread to exit; it notices within `STOP_CHECK_INTERVAL_MS`
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
 impl MouseListener {
     pub fn new(config: Config) -> Self {
         Self { config }
@@ -108,12 +126,9 @@ impl MouseListener {
         anyhow::bail!("No mouse device with side buttons found in /dev/input")
     }
 
-    /// Run the mouse event listener in a background thread
-    pub fn spawn(
-        &self,
-        wm: Arc<dyn WindowManager>,
-        state: Arc<Mutex<CycleState>>,
-    ) -> Result<std::thread::JoinHandle<()>> {
+    /// Run the mouse event listener in a background thread.
+    /// Button presses are queued on the daemon's worker so bursts get coalesced.
+    pub fn spawn(&self, commands: CommandSender) -> Result<MouseListenerHandle> {
         if !self.config.enable_mouse_buttons {
             anyhow::bail!("Mouse buttons are disabled in config");
         }
@@ -122,34 +137,33 @@ impl MouseListener {
         let backward_button = self.config.backward_button;
         let mouse_device_name = self.config.mouse_device_name.clone();
         let mouse_device_path = self.config.mouse_device_path.clone();
-        let minimize_inactive = self.config.minimize_inactive;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = Arc::clone(&stop);
 
-        let handle = std::thread::spawn(move || {
+        std::thread::spawn(move || {
             match Self::run_listener(
-                wm,
-                state,
+                commands,
+                stop_flag,
                 forward_button,
                 backward_button,
                 mouse_device_name,
                 mouse_device_path,
-                minimize_inactive,
             ) {
                 Ok(_) => println!("Mouse listener stopped"),
                 Err(e) => eprintln!("Mouse listener error: {}", e),
             }
         });
 
-        Ok(handle)
+        Ok(MouseListenerHandle { stop })
     }
 
     fn run_listener(
-        wm: Arc<dyn WindowManager>,
-        state: Arc<Mutex<CycleState>>,
+        commands: CommandSender,
+        stop: Arc<AtomicBool>,
         forward_button: u16,
         backward_button: u16,
         mouse_device_name: Option<String>,
         mouse_device_path: Option<String>,
-        minimize_inactive: bool,
     ) -> Result<()> {
         let mut device = Self::find_mouse_device(
             mouse_device_name.as_deref(),
@@ -167,7 +181,17 @@ impl MouseListener {
             forward_button, backward_button
         );
 
-        loop {
+        while !stop.load(Ordering::Relaxed) {
+            // Wait for input with a timeout so a stop request is noticed even when idle
+            // SAFETY: the fd belongs to `device`, which outlives this borrow
+            let fd = unsafe { BorrowedFd::borrow_raw(device.as_raw_fd()) };
+            let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+            match poll(&mut fds, PollTimeout::from(STOP_CHECK_INTERVAL_MS)) {
+                Ok(0) | Err(Errno::EINTR) => continue,
+                Ok(_) => {}
+                Err(e) => return Err(e.into()),
+            }
+
             for event in device.fetch_events()? {
                 if let InputEventKind::Key(key) = event.kind() {
                     let code = key.code();
@@ -176,50 +200,16 @@ impl MouseListener {
                     if event.value() == 1 {
                         if code == forward_button {
                             println!("Forward button pressed");
-                            if let Err(e) = Self::cycle_forward(&wm, &state, minimize_inactive) {
-                                eprintln!("Failed to cycle forward: {}", e);
-                            }
+                            commands.send(Command::Forward);
                         } else if code == backward_button {
                             println!("Backward button pressed");
-                            if let Err(e) = Self::cycle_backward(&wm, &state, minimize_inactive) {
-                                eprintln!("Failed to cycle backward: {}", e);
-                            }
+                            commands.send(Command::Backward);
                         }
                     }
                 }
             }
         }
-    }
-
-    fn cycle_forward(
-        wm: &Arc<dyn WindowManager>,
-        state: &Arc<Mutex<CycleState>>,
-        minimize_inactive: bool,
-    ) -> Result<()> {
-        let mut state = state.lock().unwrap();
-
-        // Sync with active window first
-        if let Ok(active) = wm.get_active_window() {
-            state.sync_with_active(active);
-        }
-
-        state.cycle_forward(&**wm, minimize_inactive)?;
-        Ok(())
-    }
-
-    fn cycle_backward(
-        wm: &Arc<dyn WindowManager>,
-        state: &Arc<Mutex<CycleState>>,
-        minimize_inactive: bool,
-    ) -> Result<()> {
-        let mut state = state.lock().unwrap();
-
-        // Sync with active window first
-        if let Ok(active) = wm.get_active_window() {
-            state.sync_with_active(active);
-        }
 
-        state.cycle_backward(&**wm, minimize_inactive)?;
         Ok(())
     }
 }
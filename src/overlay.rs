@@ -1,26 +1,219 @@
+use crate::config::{parse_color, Config, OverlayLayout, OverlayTheme, ThemePreset};
 use crate::cycle_state::CycleState;
-use crate::window_manager::WindowManager;
+use crate::daemon::{self, Command, CommandSender};
+use crate::events::Event;
+use crate::window_manager::{Capabilities, EveWindow, WindowId, WindowManager};
+use anyhow::{Context, Result};
 use eframe::egui;
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::Duration;
+
+/// How often a standalone overlay polls the window manager when no daemon is running
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Padding around the chips of the horizontal layout
+const STRIP_MARGIN: f32 = 4.0;
+
+/// Titles are cut to this many characters
+const TITLE_LENGTH: usize = 20;
+
+fn short_title(title: &str) -> String {
+    title.chars().take(TITLE_LENGTH).collect()
+}
+
+/// The number `nicotine N` reaches each client with: its line in
+/// characters.txt, or its position when there's no characters.txt. Clients
+/// missing from characters.txt can't be reached by number.
+fn target_numbers(windows: &[EveWindow], character_order: Option<&[String]>) -> Vec<Option<usize>> {
+    windows
+        .iter()
+        .enumerate()
+        .map(|(i, window)| match character_order {
+            Some(order) => order
+                .iter()
+                .position(|name| *name == window.title)
+                .map(|slot| slot + 1),
+            None => Some(i + 1),
+        })
+        .collect()
+}
+
+/// Entries for a client's "Move to slot" menu: each slot `nicotine N` can
+/// reach after the move, labelled with the character now in it. Without a
+/// characters.txt, slots follow the window order, as switching does.
+fn slot_entries(
+    window: &EveWindow,
+    windows: &[EveWindow],
+    character_order: Option<&[String]>,
+) -> Vec<(usize, String)> {
+    let occupants: Vec<&str> = match character_order {
+        Some(order) => order.iter().map(String::as_str).collect(),
+        None => windows.iter().map(|w| w.title.as_str()).collect(),
+    };
+    // A character missing from characters.txt can also go in after the last line
+    let count = if occupants.contains(&window.title.as_str()) {
+        occupants.len()
+    } else {
+        occupants.len() + 1
+    };
+
+    (1..=count)
+        .map(|slot| match occupants.get(slot - 1) {
+            Some(occupant) => (slot, format!("{} {}", slot, short_title(occupant))),
+            None => (slot, slot.to_string()),
+        })
+        .collect()
+}
 
 pub struct OverlayApp {
     wm: Arc<dyn WindowManager>,
     state: Arc<Mutex<CycleState>>,
-    config: crate::config::Config,
+    commands: Option<CommandSender>,
+    /// The daemon's own config in `nicotine start`; kept current by
+    /// `spawn_state_follower` for a standalone overlay
+    config: Arc<Mutex<Config>>,
     drag_start_window_pos: Option<egui::Pos2>,
     drag_accumulated: egui::Vec2,
-    overlay_window_id: Option<u32>,
-    last_sync: Instant,
-    last_index: usize,
+    overlay_window_id: Option<WindowId>,
+    last_size: egui::Vec2,
+    /// Size the horizontal strip took up in the last frame
+    strip_size: Mutex<Option<egui::Vec2>>,
+    capabilities: Capabilities,
+    /// The theme and the `[overlay.theme]` it was built from, rebuilt when that changes
+    theme: Mutex<(OverlayTheme, Theme)>,
+}
+
+/// Overlay colors and sizes: a preset with the `[overlay.theme]` overrides applied
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub background: egui::Color32,
+    pub border: egui::Color32,
+    pub header: egui::Color32,
+    pub header_text: egui::Color32,
+    pub text: egui::Color32,
+    pub accent: egui::Color32,
+    pub opacity: f32,
+    pub font_size: f32,
+    pub width: f32,
+    pub show_header: bool,
+    pub row_spacing: f32,
+    /// Never shorter than the original overlay: room for ten clients, then
+    /// 20px per extra one. Overrides can only make it taller.
+    pub classic_height: bool,
+}
+
+impl Theme {
+    pub fn preset(preset: ThemePreset) -> Self {
+        match preset {
+            ThemePreset::Classic => Self {
+                background: egui::Color32::from_rgb(252, 250, 242),
+                border: egui::Color32::from_rgb(180, 155, 105),
+                header: egui::Color32::from_rgb(196, 30, 58),
+                header_text: egui::Color32::from_rgb(252, 250, 242),
+                text: egui::Color32::from_rgb(30, 30, 30),
+                accent: egui::Color32::from_rgb(196, 30, 58),
+                opacity: 1.0,
+                font_size: 13.0,
+                width: 220.0,
+                show_header: true,
+                row_spacing: 2.0,
+                classic_height: true,
+            },
+            ThemePreset::Dark => Self {
+                background: egui::Color32::from_rgb(30, 30, 30),
+                border: egui::Color32::from_rgb(70, 70, 70),
+                header: egui::Color32::from_rgb(45, 45, 45),
+                header_text: egui::Color32::from_rgb(230, 230, 230),
+                text: egui::Color32::from_rgb(200, 200, 200),
+                accent: egui::Color32::from_rgb(232, 176, 74),
+                opacity: 0.9,
+                font_size: 13.0,
+                width: 220.0,
+                show_header: true,
+                row_spacing: 2.0,
+                classic_height: false,
+            },
+            ThemePreset::CompactDark => Self {
+                font_size: 11.0,
+                width: 160.0,
+                show_header: false,
+                row_spacing: 0.0,
+                opacity: 0.85,
+                ..Self::preset(ThemePreset::Dark)
+            },
+        }
+    }
+
+    pub fn from_config(config: &OverlayTheme) -> Self {
+        let mut theme = Self::preset(config.preset);
+
+        // Colors are checked when the config is loaded
+        let color = |value: &Option<String>, fallback: egui::Color32| {
+            value
+                .as_deref()
+                .and_then(parse_color)
+                .map_or(fallback, |[r, g, b]| egui::Color32::from_rgb(r, g, b))
+        };
+        theme.background = color(&config.background, theme.background);
+        theme.border = color(&config.border, theme.border);
+        theme.header = color(&config.header, theme.header);
+        theme.header_text = color(&config.header_text, theme.header_text);
+        theme.text = color(&config.text, theme.text);
+        theme.accent = color(&config.accent, theme.accent);
+
+        theme.opacity = config.opacity.unwrap_or(theme.opacity);
+        theme.font_size = config.font_size.unwrap_or(theme.font_size);
+        theme.width = config.width.unwrap_or(theme.width);
+        theme.show_header = config.show_header.unwrap_or(theme.show_header);
+        theme.row_spacing = config.row_spacing.unwrap_or(theme.row_spacing);
+        theme
+    }
+
+    fn row_height(&self) -> f32 {
+        self.font_size + 4.0
+    }
+
+    fn header_height(&self) -> f32 {
+        if self.show_header {
+            70.0
+        } else {
+            10.0
+        }
+    }
+
+    /// Starting size of the horizontal strip, before its chips are measured
+    fn strip_size(&self) -> egui::Vec2 {
+        egui::vec2(self.width, self.row_height() + 8.0 + STRIP_MARGIN * 2.0)
+    }
+
+    /// Smallest the eframe window can be resized to. The horizontal strip
+    /// shrinks to fit its chips, so only the panel has a minimum.
+    fn min_size(&self, layout: OverlayLayout) -> egui::Vec2 {
+        match layout {
+            OverlayLayout::Vertical => self.size(0, true),
+            OverlayLayout::Horizontal => egui::Vec2::ZERO,
+        }
+    }
+
+    /// Overlay size with `client_count` rows, and the restack button if it's shown
+    pub fn size(&self, client_count: usize, restack: bool) -> egui::Vec2 {
+        let rows = client_count.max(1) as f32 * (self.row_height() + self.row_spacing);
+        let footer = if restack { self.font_size + 40.0 } else { 10.0 };
+        let mut height = self.header_height() + rows + footer;
+        if self.classic_height {
+            height = height.max(320.0 + client_count.saturating_sub(10) as f32 * 20.0);
+        }
+        egui::vec2(self.width, height)
+    }
 }
 
 impl OverlayApp {
     pub fn new(
-        cc: &eframe::CreationContext<'_>,
+        ctx: &egui::Context,
         wm: Arc<dyn WindowManager>,
         state: Arc<Mutex<CycleState>>,
-        config: crate::config::Config,
+        commands: Option<CommandSender>,
+        config: Arc<Mutex<Config>>,
     ) -> Self {
         let mut fonts = egui::FontDefinitions::default();
 
@@ -54,167 +247,432 @@ impl OverlayApp {
             .or_default()
             .push("logo_font".to_owned());
 
-        cc.egui_ctx.set_fonts(fonts);
+        ctx.set_fonts(fonts);
+
+        let overlay_theme = config.lock().unwrap().overlay.theme.clone();
+        let theme = Mutex::new((overlay_theme.clone(), Theme::from_config(&overlay_theme)));
 
         Self {
-            wm,
             state,
-            config,
+            commands,
             drag_start_window_pos: None,
             drag_accumulated: egui::Vec2::ZERO,
             overlay_window_id: None,
-            last_sync: Instant::now(),
-            last_index: 0,
+            last_size: egui::Vec2::ZERO,
+            strip_size: Mutex::new(None),
+            capabilities: wm.capabilities(),
+            theme,
+            config,
+            wm,
         }
     }
 }
 
-impl eframe::App for OverlayApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Request repaint for smooth updates
-        ctx.request_repaint();
+impl OverlayApp {
+    pub fn client_count(&self) -> usize {
+        self.state.lock().unwrap().get_windows().len()
+    }
 
-        // Read current index from file (instant, no process spawning)
-        if let Some(index) = CycleState::read_index_from_file() {
-            if index != self.last_index {
-                self.last_index = index;
-                let mut state = self.state.lock().unwrap();
-                state.set_current_index(index);
-            }
+    fn theme(&self) -> Theme {
+        let config = self.config.lock().unwrap();
+        let mut theme = self.theme.lock().unwrap();
+        if theme.0 != config.overlay.theme {
+            *theme = (
+                config.overlay.theme.clone(),
+                Theme::from_config(&config.overlay.theme),
+            );
         }
+        theme.1.clone()
+    }
 
-        // Periodic full sync for window list updates (new clients, etc)
-        let now = Instant::now();
-        if now.duration_since(self.last_sync).as_millis() >= 500 {
-            self.last_sync = now;
-
-            if let Ok(windows) = self.wm.get_eve_windows() {
-                let mut state = self.state.lock().unwrap();
-                state.update_windows(windows);
-
-                // Resize window based on client count
-                let client_count = state.get_windows().len();
-                let base_height = 320.0_f32;
-                let per_client = 20.0_f32;
-                let min_clients = 10;
-                let extra_clients = client_count.saturating_sub(min_clients);
-                let target_height = base_height + (extra_clients as f32 * per_client);
-
-                ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(
-                    220.0,
-                    target_height,
-                )));
-            }
+    fn layout(&self) -> OverlayLayout {
+        self.config.lock().unwrap().overlay.layout
+    }
+
+    /// Size the overlay needs for the current clients
+    pub fn size(&self) -> egui::Vec2 {
+        match self.layout() {
+            OverlayLayout::Vertical => self
+                .theme()
+                .size(self.client_count(), self.capabilities.resize),
+            // Only known once the chips have been laid out
+            OverlayLayout::Horizontal => self
+                .strip_size
+                .lock()
+                .unwrap()
+                .unwrap_or_else(|| self.theme().strip_size()),
         }
+    }
+
+    /// Draw the overlay contents. Shared by the eframe window and the
+    /// layer-shell surface, which each handle sizing themselves.
+    pub fn show(&self, ctx: &egui::Context) {
+        match self.layout() {
+            OverlayLayout::Vertical => self.show_vertical(ctx),
+            OverlayLayout::Horizontal => self.show_horizontal(ctx),
+        }
+    }
+
+    /// One strip of chips like "2 Alpha", the active client highlighted
+    fn show_horizontal(&self, ctx: &egui::Context) {
+        let theme = &self.theme();
+
+        egui::CentralPanel::default()
+            .frame(
+                egui::Frame::none()
+                    .fill(theme.background.gamma_multiply(theme.opacity))
+                    .inner_margin(STRIP_MARGIN)
+                    .stroke(egui::Stroke::new(1.0, theme.border)),
+            )
+            .show(ctx, |ui| {
+                let state = self.state.lock().unwrap();
+                let windows = state.get_windows();
+                let current_index = state.get_current_index();
+                let order = state.character_order();
+                let numbers = target_numbers(windows, order);
+                // Run after the state lock is released
+                let mut command = None;
+
+                let strip = ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing.x = 4.0;
+
+                    for (i, window) in windows.iter().enumerate() {
+                        let is_active = i == current_index;
+                        let title = short_title(&window.title);
+                        let label = match numbers[i] {
+                            Some(number) => format!("{} {}", number, title),
+                            None => title,
+                        };
+
+                        let (fill, text_color) = if is_active {
+                            (theme.accent, theme.header_text)
+                        } else {
+                            (egui::Color32::TRANSPARENT, theme.text)
+                        };
+                        let chip = egui::Button::new(
+                            egui::RichText::new(label)
+                                .size(theme.font_size)
+                                .color(text_color),
+                        )
+                        .fill(fill)
+                        .stroke(egui::Stroke::new(1.0, theme.border))
+                        .rounding(3.0);
+
+                        let response = ui.add(chip);
+                        if let Some(picked) = self.client_command(&response, window, windows, order)
+                        {
+                            command = Some(picked);
+                        }
+                    }
+
+                    if windows.is_empty() {
+                        ui.colored_label(theme.border, "No clients");
+                    }
+
+                    if self.capabilities.resize {
+                        let label = egui::RichText::new("STACK")
+                            .color(theme.header_text)
+                            .size(theme.font_size - 1.0);
+                        let button = egui::Button::new(label).fill(theme.header).rounding(3.0);
+                        if ui.add(button).clicked() {
+                            command = Some(Command::Stack);
+                        }
+                    }
+                });
+
+                drop(state);
+                *self.strip_size.lock().unwrap() =
+                    Some(strip.response.rect.size() + egui::Vec2::splat(STRIP_MARGIN * 2.0));
+                if let Some(command) = command {
+                    self.run(command);
+                }
+            });
+    }
 
-        let red = egui::Color32::from_rgb(196, 30, 58);
-        let gold = egui::Color32::from_rgb(180, 155, 105);
-        let cream = egui::Color32::from_rgb(252, 250, 242);
-        let black = egui::Color32::from_rgb(30, 30, 30);
+    fn show_vertical(&self, ctx: &egui::Context) {
+        let theme = &self.theme();
 
         let _panel_response = egui::CentralPanel::default()
             .frame(
                 egui::Frame::none()
-                    .fill(cream)
+                    .fill(theme.background.gamma_multiply(theme.opacity))
                     .rounding(0.0)
                     .inner_margin(0.0)
-                    .stroke(egui::Stroke::new(2.0, gold)),
+                    .stroke(egui::Stroke::new(2.0, theme.border)),
             )
             .show(ctx, |ui| {
-                // Red top bar
-                let rect = ui.available_rect_before_wrap();
-                ui.painter().rect_filled(
-                    egui::Rect::from_min_size(rect.min, egui::vec2(rect.width(), 44.0)),
-                    0.0,
-                    red,
-                );
-
-                // NICOTINE text in red bar
-                ui.add_space(10.0);
-                ui.vertical_centered(|ui| {
-                    ui.label(
-                        egui::RichText::new("Nicotine")
-                            .family(egui::FontFamily::Name("logo".into()))
-                            .size(32.0)
-                            .color(cream),
+                if theme.show_header {
+                    // Header bar
+                    let rect = ui.available_rect_before_wrap();
+                    ui.painter().rect_filled(
+                        egui::Rect::from_min_size(rect.min, egui::vec2(rect.width(), 44.0)),
+                        0.0,
+                        theme.header.gamma_multiply(theme.opacity),
                     );
-                });
 
-                ui.add_space(16.0);
+                    // NICOTINE text in the header
+                    ui.add_space(10.0);
+                    ui.vertical_centered(|ui| {
+                        ui.label(
+                            egui::RichText::new("Nicotine")
+                                .family(egui::FontFamily::Name("logo".into()))
+                                .size(32.0)
+                                .color(theme.header_text),
+                        );
+                    });
+                }
+
+                ui.add_space(if theme.show_header { 16.0 } else { 8.0 });
 
                 // Client list
                 egui::Frame::none()
-                    .inner_margin(egui::Margin::symmetric(16.0, 0.0))
+                    .inner_margin(egui::Margin::symmetric(
+                        if theme.show_header { 16.0 } else { 8.0 },
+                        0.0,
+                    ))
                     .show(ui, |ui| {
                         let state = self.state.lock().unwrap();
                         let windows = state.get_windows();
                         let current_index = state.get_current_index();
+                        let order = state.character_order();
+                        // Run after the state lock is released
+                        let mut command = None;
 
                         for (i, window) in windows.iter().enumerate() {
                             let is_active = i == current_index;
-                            let display_title = &window.title[..window.title.len().min(20)];
+                            let display_title = short_title(&window.title);
 
-                            let text_color = if is_active { red } else { black };
+                            let text_color = if is_active { theme.accent } else { theme.text };
                             let prefix = if is_active { "▸ " } else { "  " };
 
-                            ui.colored_label(
+                            // The whole row is the click target, not just the text
+                            let (rect, response) = ui.allocate_exact_size(
+                                egui::vec2(ui.available_width(), theme.row_height()),
+                                egui::Sense::click(),
+                            );
+                            if response.hovered() {
+                                ui.painter().rect_filled(
+                                    rect.expand2(egui::vec2(6.0, 0.0)),
+                                    2.0,
+                                    theme.border.gamma_multiply(0.3),
+                                );
+                            }
+                            ui.painter().text(
+                                rect.left_center(),
+                                egui::Align2::LEFT_CENTER,
+                                format!("{}{}", prefix, display_title),
+                                egui::FontId::proportional(theme.font_size),
                                 text_color,
-                                egui::RichText::new(format!("{}{}", prefix, display_title))
-                                    .size(13.0)
-                                    .strong(),
                             );
-                            ui.add_space(2.0);
+
+                            if let Some(picked) =
+                                self.client_command(&response, window, windows, order)
+                            {
+                                command = Some(picked);
+                            }
+                            ui.add_space(theme.row_spacing);
                         }
 
                         if windows.is_empty() {
                             ui.add_space(10.0);
                             ui.vertical_centered(|ui| {
-                                ui.colored_label(gold, "No clients");
+                                ui.colored_label(theme.border, "No clients");
                             });
                         }
+
+                        drop(state);
+                        if let Some(command) = command {
+                            self.run(command);
+                        }
                     });
 
-                // Bottom button
+                // Bottom button, unless the backend can't place windows
+                if !self.capabilities.resize {
+                    return;
+                }
                 ui.with_layout(egui::Layout::bottom_up(egui::Align::Center), |ui| {
                     ui.add_space(10.0);
 
-                    let button =
-                        egui::Button::new(egui::RichText::new("RESTACK").color(cream).size(12.0))
-                            .fill(red)
-                            .rounding(2.0);
+                    let label = egui::RichText::new("RESTACK")
+                        .color(theme.header_text)
+                        .size(theme.font_size - 1.0);
+                    let button = egui::Button::new(label).fill(theme.accent).rounding(2.0);
 
                     if ui.add(button).clicked() {
-                        let wm_clone = Arc::clone(&self.wm);
-                        let config = self.config.clone();
-                        std::thread::spawn(move || {
-                            if let Ok(windows) = wm_clone.get_eve_windows() {
-                                let _ = wm_clone.stack_windows(&windows, &config);
-                            }
-                        });
+                        self.run(Command::Stack);
                     }
 
                     ui.add_space(6.0);
                 });
             });
+    }
+
+    /// What a click or the context menu on a client's row or chip asks for
+    fn client_command(
+        &self,
+        response: &egui::Response,
+        window: &EveWindow,
+        windows: &[EveWindow],
+        character_order: Option<&[String]>,
+    ) -> Option<Command> {
+        let mut command = response.clicked().then_some(Command::Focus(window.id));
+        // Popups can't leave the window or layer surface, and the strip is
+        // only one chip tall, so the menu would be cut off there
+        if self.layout() == OverlayLayout::Horizontal {
+            return command;
+        }
+        response.context_menu(|ui| {
+            if let Some(picked) = self.client_menu(ui, window, windows, character_order) {
+                command = Some(picked);
+                ui.close_menu();
+            }
+        });
+        command
+    }
+
+    /// Right-click menu for one client row; returns the command picked, if any.
+    /// Entries the backend can't carry out are left out.
+    fn client_menu(
+        &self,
+        ui: &mut egui::Ui,
+        window: &EveWindow,
+        windows: &[EveWindow],
+        character_order: Option<&[String]>,
+    ) -> Option<Command> {
+        let mut picked = None;
+
+        if self.capabilities.minimize {
+            if ui.button("Minimize").clicked() {
+                picked = Some(Command::Minimize(window.id));
+            }
+            if ui.button("Restore").clicked() {
+                picked = Some(Command::Restore(window.id));
+            }
+        }
+        if self.capabilities.resize && ui.button("Restack").clicked() {
+            picked = Some(Command::StackWindow(window.id));
+        }
+        ui.menu_button("Move to slot", |ui| {
+            for (slot, label) in slot_entries(window, windows, character_order) {
+                if ui.button(label).clicked() {
+                    picked = Some(Command::AssignSlot {
+                        window_id: window.id,
+                        slot,
+                    });
+                }
+            }
+        });
+
+        picked
+    }
+
+    /// Carry out a command from the overlay. Inside the daemon process it's
+    /// queued on the worker; a standalone overlay goes through the daemon when
+    /// it's running, so subscribers see the change, and does it itself otherwise.
+    fn run(&self, command: Command) {
+        if let Some(commands) = &self.commands {
+            commands.send(command);
+            return;
+        }
+
+        let wm = Arc::clone(&self.wm);
+        let state = Arc::clone(&self.state);
+        let config = self.config.lock().unwrap().clone();
+        std::thread::spawn(move || {
+            if daemon::send_command(command.clone()).is_ok() {
+                return;
+            }
+            if let Err(e) = run_without_daemon(&*wm, &state, &config, command) {
+                eprintln!("Overlay error: {:#}", e);
+            }
+        });
+    }
+}
+
+/// What the daemon's worker would do for an overlay command, for when no daemon is running
+fn run_without_daemon(
+    wm: &dyn WindowManager,
+    state: &Mutex<CycleState>,
+    config: &Config,
+    command: Command,
+) -> Result<()> {
+    let mut state = state.lock().unwrap();
+    let client = |state: &CycleState, window_id| {
+        state
+            .get_window(window_id)
+            .cloned()
+            .with_context(|| format!("Window {} is not an EVE client", window_id))
+    };
+
+    match command {
+        Command::Stack => wm.stack_windows(&wm.get_eve_windows()?, config),
+        Command::Focus(window_id) => state.focus(window_id, wm, config.minimize_inactive),
+        Command::Minimize(window_id) => wm.minimize_window(window_id),
+        Command::Restore(window_id) => wm.restore_window(window_id),
+        Command::StackWindow(window_id) => wm.stack_windows(&[client(&state, window_id)?], config),
+        Command::AssignSlot { window_id, slot } => {
+            let window = client(&state, window_id)?;
+            let clients: Vec<String> = state
+                .get_windows()
+                .iter()
+                .map(|w| w.title.clone())
+                .collect();
+            let character_order = Config::assign_slot(&window.title, slot, &clients)?;
+            state.set_character_order(Some(character_order));
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+impl eframe::App for OverlayApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Request repaint for smooth updates
+        ctx.request_repaint();
+
+        // The state is kept current by the daemon (or `spawn_state_follower`);
+        // only resize when the clients need a different size
+        let size = self.size();
+        if size != self.last_size {
+            self.last_size = size;
+            // The minimum follows theme and layout changes on reload
+            let min_size = self.theme().min_size(self.layout());
+            ctx.send_viewport_cmd(egui::ViewportCommand::MinInnerSize(min_size));
+            ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
+        }
+
+        self.show(ctx);
 
-        // Handle dragging with middle mouse button
-        // Note: Overlay dragging is X11-only. On Wayland, use your compositor's window
-        // management features to position the overlay window.
+        // Handle dragging with middle mouse button, where the backend can move
+        // windows. Elsewhere, use your compositor's window management features
+        // to position the overlay window.
+        if !self.capabilities.move_windows {
+            return;
+        }
         let middle_down = ctx.input(|i| i.pointer.button_down(egui::PointerButton::Middle));
 
         if middle_down {
             // Initialize drag if just started
             if self.drag_start_window_pos.is_none() {
-                if let Some(window_pos) = ctx.input(|i| i.viewport().outer_rect).map(|r| r.min) {
+                // Cache the window ID once at the start
+                if self.overlay_window_id.is_none() {
+                    if let Ok(Some(id)) = self.wm.find_window_by_title("Nicotine") {
+                        self.overlay_window_id = Some(id);
+                    }
+                }
+
+                // winit can't tell where the window is on Wayland; ask the backend
+                let window_pos = ctx
+                    .input(|i| i.viewport().outer_rect)
+                    .map(|r| r.min)
+                    .or_else(|| {
+                        let id = self.overlay_window_id?;
+                        let (x, y) = self.wm.window_position(id).ok()??;
+                        Some(egui::pos2(x as f32, y as f32))
+                    });
+                if let Some(window_pos) = window_pos {
                     self.drag_start_window_pos = Some(window_pos);
                     self.drag_accumulated = egui::Vec2::ZERO;
-
-                    // Cache the window ID once at the start
-                    if self.overlay_window_id.is_none() {
-                        if let Ok(Some(id)) = self.wm.find_window_by_title("Nicotine") {
-                            self.overlay_window_id = Some(id);
-                        }
-                    }
                 }
             }
 
@@ -247,18 +705,153 @@ impl eframe::App for OverlayApp {
     }
 }
 
+/// Keep `state` and `config` current for an overlay running in its own
+/// process: follow the daemon's event stream while it's running, otherwise
+/// poll the window manager
+pub fn spawn_state_follower(
+    wm: Arc<dyn WindowManager>,
+    state: Arc<Mutex<CycleState>>,
+    config: Arc<Mutex<Config>>,
+) {
+    std::thread::spawn(move || loop {
+        if let Ok(events) = daemon::subscribe() {
+            // Start from the daemon's view, then apply each change as it comes
+            let mut in_sync = sync_from_daemon(&state).is_ok();
+            for event in events {
+                let Ok(event) = event else { break };
+                if event == Event::ConfigReloaded {
+                    // The daemon has just validated both files
+                    if let Ok(reloaded) = Config::reload() {
+                        *config.lock().unwrap() = reloaded;
+                    }
+                    if let Ok(character_order) = Config::load_characters() {
+                        state.lock().unwrap().set_character_order(character_order);
+                    }
+                }
+                if !in_sync || !apply_event(&mut state.lock().unwrap(), &event) {
+                    in_sync = sync_from_daemon(&state).is_ok();
+                }
+            }
+            continue;
+        }
+
+        if let Ok(windows) = wm.get_eve_windows() {
+            let active = wm.get_active_window();
+            // An invalid characters.txt keeps the order we had
+            let character_order = Config::load_characters();
+            let mut state = state.lock().unwrap();
+            state.update_windows(windows);
+            if let Ok(character_order) = character_order {
+                state.set_character_order(character_order);
+            }
+            if let Ok(active) = active {
+                state.sync_with_active(active);
+            }
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    });
+}
+
+/// Apply one of the daemon's client or focus events to our copy of its state.
+/// Returns false if the event doesn't fit the copy, which then needs a full sync.
+fn apply_event(state: &mut CycleState, event: &Event) -> bool {
+    let active = state
+        .get_windows()
+        .get(state.get_current_index())
+        .map(|w| w.id);
+    let mut windows = state.get_windows().to_vec();
+
+    match event {
+        Event::ClientAdded {
+            index,
+            window_id,
+            character,
+        } => {
+            if *index == 0 || *index > windows.len() + 1 || state.get_window(*window_id).is_some() {
+                return false;
+            }
+            windows.insert(
+                index - 1,
+                EveWindow {
+                    id: *window_id,
+                    title: character.clone(),
+                },
+            );
+        }
+        Event::ClientRemoved { window_id, .. } => {
+            if state.get_window(*window_id).is_none() {
+                return false;
+            }
+            windows.retain(|w| w.id != *window_id);
+        }
+        Event::FocusChanged {
+            index, window_id, ..
+        } => {
+            let position = windows.iter().position(|w| w.id == *window_id);
+            if position.map(|i| i + 1) != Some(*index) {
+                return false;
+            }
+            state.set_current_index(index - 1);
+            return true;
+        }
+        _ => return true,
+    }
+
+    // Stay on the same client; if it went away, `focus_changed` follows
+    state.update_windows(windows);
+    if let Some(active) = active {
+        state.sync_with_active(active);
+    }
+    true
+}
+
+/// Mirror the daemon's client list and active client into `state`
+fn sync_from_daemon(state: &Mutex<CycleState>) -> Result<()> {
+    let report = daemon::send_command(Command::Status)?.into_status()?;
+
+    let windows = report
+        .clients
+        .iter()
+        .map(|client| EveWindow {
+            id: client.window_id,
+            title: client.character.clone(),
+        })
+        .collect();
+
+    let mut state = state.lock().unwrap();
+    state.update_windows(windows);
+    state.set_character_order(report.character_order);
+    if let Some(active) = report.clients.iter().position(|c| c.active) {
+        state.set_current_index(active);
+    }
+    Ok(())
+}
+
 pub fn run_overlay(
     wm: Arc<dyn WindowManager>,
     state: Arc<Mutex<CycleState>>,
-    overlay_x: f32,
-    overlay_y: f32,
-    config: crate::config::Config,
-) -> Result<(), eframe::Error> {
+    commands: Option<CommandSender>,
+    config: Arc<Mutex<Config>>,
+) -> Result<()> {
+    let initial = config.lock().unwrap().clone();
+
+    // Layer-shell keeps us above fullscreen clients, which an xdg toplevel can't do
+    if crate::layer_overlay::is_supported() {
+        let ctx = egui::Context::default();
+        let app = OverlayApp::new(&ctx, wm, state, commands, config);
+        return crate::layer_overlay::run(ctx, app, &initial.overlay);
+    }
+
+    let theme = Theme::from_config(&initial.overlay.theme);
+    let size = match initial.overlay.layout {
+        OverlayLayout::Vertical => theme.size(0, true),
+        OverlayLayout::Horizontal => theme.strip_size(),
+    };
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
-            .with_inner_size([220.0, 320.0])
-            .with_min_inner_size([220.0, 320.0])
-            .with_position([overlay_x, overlay_y])
+            .with_inner_size(size)
+            .with_min_inner_size(theme.min_size(initial.overlay.layout))
+            .with_position([initial.overlay_x, initial.overlay_y])
             .with_decorations(false)
             .with_always_on_top()
             .with_transparent(true)
@@ -284,7 +877,142 @@ pub fn run_overlay(
                     }
                 }
             });
-            Ok(Box::new(OverlayApp::new(cc, wm, state, config)))
+            Ok(Box::new(OverlayApp::new(
+                &cc.egui_ctx,
+                wm,
+                state,
+                commands,
+                config,
+            )))
         }),
     )
+    .map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_overrides_apply_on_top_of_preset() {
+        let theme = Theme::from_config(&OverlayTheme {
+            preset: ThemePreset::CompactDark,
+            accent: Some("#50a0ff".to_string()),
+            font_size: Some(12.0),
+            ..Default::default()
+        });
+
+        assert_eq!(theme.accent, egui::Color32::from_rgb(0x50, 0xa0, 0xff));
+        assert_eq!(theme.font_size, 12.0);
+        // Everything else comes from the preset
+        assert_eq!(theme.width, 160.0);
+        assert!(!theme.show_header);
+        assert_eq!(
+            theme.background,
+            Theme::preset(ThemePreset::Dark).background
+        );
+    }
+
+    #[test]
+    fn test_events_keep_a_copy_of_the_state_in_step() {
+        let window = |id, title: &str| EveWindow {
+            id: WindowId(id),
+            title: title.to_string(),
+        };
+        let events = Arc::new(crate::events::EventBus::new());
+        let rx = events.subscribe();
+        let mut daemon = CycleState::with_events(Arc::clone(&events));
+        let mut copy = CycleState::new();
+
+        daemon.update_windows(vec![window(1, "A"), window(2, "B"), window(3, "C")]);
+        daemon.sync_with_active(WindowId(3));
+        // Clients leave and join on both sides of the active one
+        daemon.update_windows(vec![window(4, "D"), window(5, "E"), window(3, "C")]);
+        daemon.update_windows(vec![window(5, "E"), window(6, "F")]);
+
+        for event in rx.try_iter() {
+            assert!(apply_event(&mut copy, &event), "{:?}", event);
+        }
+        assert_eq!(copy.get_windows(), daemon.get_windows());
+        assert_eq!(copy.get_current_index(), daemon.get_current_index());
+
+        // Focus on a client the copy doesn't have calls for a full sync
+        assert!(!apply_event(
+            &mut copy,
+            &Event::FocusChanged {
+                index: 1,
+                window_id: WindowId(9),
+                character: "Z".to_string(),
+            }
+        ));
+    }
+
+    #[test]
+    fn test_target_numbers() {
+        let windows = vec![
+            EveWindow {
+                id: WindowId(1),
+                title: "Beta".to_string(),
+            },
+            EveWindow {
+                id: WindowId(2),
+                title: "Alpha".to_string(),
+            },
+        ];
+        assert_eq!(target_numbers(&windows, None), vec![Some(1), Some(2)]);
+
+        let order = vec!["Alpha".to_string(), "Gamma".to_string()];
+        assert_eq!(target_numbers(&windows, Some(&order)), vec![None, Some(1)]);
+    }
+
+    #[test]
+    fn test_slot_entries_follow_switch_order() {
+        let windows = vec![
+            EveWindow {
+                id: WindowId(1),
+                title: "Beta".to_string(),
+            },
+            EveWindow {
+                id: WindowId(2),
+                title: "Alpha".to_string(),
+            },
+        ];
+        let entries = |window: usize, order: Option<&[String]>| -> Vec<String> {
+            slot_entries(&windows[window], &windows, order)
+                .into_iter()
+                .map(|(_, label)| label)
+                .collect()
+        };
+
+        assert_eq!(entries(0, None), vec!["1 Beta", "2 Alpha"]);
+
+        // characters.txt may list more characters than are logged in
+        let order: Vec<String> = ["Alpha", "Gamma", "Delta"].map(String::from).to_vec();
+        assert_eq!(
+            entries(1, Some(&order)),
+            vec!["1 Alpha", "2 Gamma", "3 Delta"]
+        );
+        // Beta isn't listed, so it can also take a new last line
+        assert_eq!(
+            entries(0, Some(&order)),
+            vec!["1 Alpha", "2 Gamma", "3 Delta", "4"]
+        );
+    }
+
+    #[test]
+    fn test_theme_size_follows_clients() {
+        let classic = Theme::preset(ThemePreset::Classic);
+        let dark = Theme::preset(ThemePreset::Dark);
+        let compact = Theme::preset(ThemePreset::CompactDark);
+
+        let three = dark.size(3, true);
+        assert_eq!(three.x, 220.0);
+        assert_eq!(dark.size(4, true).y - three.y, 19.0);
+        assert!(dark.size(3, false).y < three.y);
+        assert!(compact.size(3, true).y < three.y);
+
+        // Classic keeps the original sizing
+        assert_eq!(classic.size(3, true).y, 320.0);
+        assert_eq!(classic.size(12, true).y, 360.0);
+    }
 }
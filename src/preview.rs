@@ -0,0 +1,670 @@
+use crate::config::{parse_color, PreviewConfig};
+use crate::cycle_state::CycleState;
+use crate::daemon::{Command, CommandSender};
+use crate::window_manager::{EveWindow, WindowId};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::composite::{self, ConnectionExt as _, Redirect};
+use x11rb::protocol::damage::{self, ConnectionExt as _, Damage, ReportLevel};
+use x11rb::protocol::render::{
+    self, ConnectionExt as _, CreatePictureAux, Fixed, PictOp, Pictformat, Picture, Transform,
+};
+use x11rb::protocol::xproto::*;
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+
+/// Time between checks for damage and client changes, roughly 15 a second
+const FRAME_INTERVAL: Duration = Duration::from_millis(66);
+
+/// Left mouse button
+const BUTTON_PRIMARY: u8 = 1;
+
+/// Handle to the running thumbnails, used to take them down again on reload
+pub struct PreviewHandle {
+    stop: Arc<AtomicBool>,
+}
+
+impl PreviewHandle {
+    /// Ask the preview thread to exit; its windows go away with its connection
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Show a live thumbnail for each client in `state`, until stopped. Clicking
+/// a thumbnail sends `Command::Focus` for its client.
+///
+/// Each client window is redirected off-screen with Composite, so its contents
+/// stay available while it's covered, and scaled into the thumbnail with
+/// XRender on the server. Thumbnails are only redrawn when Damage reports a
+/// change to their client.
+pub fn spawn(
+    config: PreviewConfig,
+    state: Arc<Mutex<CycleState>>,
+    commands: CommandSender,
+) -> Result<PreviewHandle> {
+    // Connect up front so a missing extension is reported to the caller
+    let previews = Previews::connect(config)?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_flag = Arc::clone(&stop);
+
+    std::thread::spawn(move || {
+        if let Err(e) = previews.run(&state, &commands, &stop_flag) {
+            eprintln!("Preview error: {:#}", e);
+        }
+    });
+
+    Ok(PreviewHandle { stop })
+}
+
+/// Where each client's thumbnail goes: its entry in `positions`, otherwise
+/// the next spot in the row. Placed clients leave no gap in the row.
+fn thumbnail_positions(config: &PreviewConfig, windows: &[EveWindow]) -> Vec<(i16, i16)> {
+    let step = i32::from(config.width) + i32::from(config.spacing);
+    let mut next_x = i32::from(config.x);
+
+    windows
+        .iter()
+        .map(|window| {
+            if let Some(&[x, y]) = config.positions.get(&window.title) {
+                return (x, y);
+            }
+            let x = next_x;
+            next_x += step;
+            (x.clamp(i16::MIN.into(), i16::MAX.into()) as i16, config.y)
+        })
+        .collect()
+}
+
+/// Size of the client image inside a thumbnail's border
+fn content_size(config: &PreviewConfig) -> (u16, u16) {
+    let border = config.border_width.saturating_mul(2);
+    (
+        config.width.saturating_sub(border).max(1),
+        config.height.saturating_sub(border).max(1),
+    )
+}
+
+/// The four strips of a `width` x `height` thumbnail covered by its border
+fn border_rects(width: u16, height: u16, border: u16) -> [Rectangle; 4] {
+    let b = border.min(width / 2).min(height / 2);
+    let inner_height = height - 2 * b;
+    [
+        Rectangle {
+            x: 0,
+            y: 0,
+            width,
+            height: b,
+        },
+        Rectangle {
+            x: 0,
+            y: (height - b) as i16,
+            width,
+            height: b,
+        },
+        Rectangle {
+            x: 0,
+            y: b as i16,
+            width: b,
+            height: inner_height,
+        },
+        Rectangle {
+            x: (width - b) as i16,
+            y: b as i16,
+            width: b,
+            height: inner_height,
+        },
+    ]
+}
+
+fn to_fixed(value: f64) -> Fixed {
+    (value * 65536.0).round() as Fixed
+}
+
+/// Picture transform that scales a `source`-sized image down to `target`.
+/// XRender transforms map destination pixels back to source pixels.
+fn scale_transform(source: (u16, u16), target: (u16, u16)) -> Transform {
+    let one = to_fixed(1.0);
+    Transform {
+        matrix11: to_fixed(f64::from(source.0) / f64::from(target.0.max(1))),
+        matrix12: 0,
+        matrix13: 0,
+        matrix21: 0,
+        matrix22: to_fixed(f64::from(source.1) / f64::from(target.1.max(1))),
+        matrix23: 0,
+        matrix31: 0,
+        matrix32: 0,
+        matrix33: one,
+    }
+}
+
+/// `_NET_WM_WINDOW_OPACITY` value for an opacity between 0.0 and 1.0
+fn opacity_value(opacity: f32) -> u32 {
+    (f64::from(opacity.clamp(0.0, 1.0)) * f64::from(u32::MAX)).round() as u32
+}
+
+/// XRender colors use 16 bits per channel
+fn render_color([r, g, b]: [u8; 3]) -> render::Color {
+    render::Color {
+        red: u16::from(r) * 257,
+        green: u16::from(g) * 257,
+        blue: u16::from(b) * 257,
+        alpha: 0xffff,
+    }
+}
+
+/// Thumbnail window for one client
+struct Thumbnail {
+    window: Window,
+    picture: Picture,
+    position: (i16, i16),
+    /// Named pixmap of the client and a scaled picture of it; None until the
+    /// client is next viewable
+    source: Option<(Pixmap, Picture)>,
+    /// Client size `source` was named at; a ConfigureNotify that keeps it
+    /// only moved the client, which leaves its pixmap in place
+    size: (u16, u16),
+    /// Reports changes to the client's contents
+    damage: Damage,
+    /// Whether to redraw the thumbnail on the next frame
+    dirty: bool,
+    /// Whether to try naming the client's pixmap on the next draw: once when
+    /// the thumbnail is created, then each time the client is mapped or
+    /// resized. Unmapped clients stay black without costing round trips.
+    bind_pending: bool,
+}
+
+struct Previews {
+    conn: RustConnection,
+    root: Window,
+    root_depth: u8,
+    root_visual: Visualid,
+    formats: render::QueryPictFormatsReply,
+    opacity_atom: Atom,
+    config: PreviewConfig,
+    border_color: render::Color,
+    /// Keyed by the client window
+    thumbnails: HashMap<Window, Thumbnail>,
+}
+
+impl Previews {
+    fn connect(config: PreviewConfig) -> Result<Self> {
+        let (conn, screen_num) =
+            RustConnection::connect(None).context("Failed to connect to X11 server")?;
+
+        if conn
+            .extension_information(composite::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            anyhow::bail!("The X server doesn't support the Composite extension");
+        }
+        if conn
+            .extension_information(render::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            anyhow::bail!("The X server doesn't support the Render extension");
+        }
+        if conn
+            .extension_information(damage::X11_EXTENSION_NAME)?
+            .is_none()
+        {
+            anyhow::bail!("The X server doesn't support the Damage extension");
+        }
+        // NameWindowPixmap needs Composite 0.2, picture transforms Render 0.6
+        conn.composite_query_version(0, 4)?.reply()?;
+        conn.render_query_version(0, 11)?.reply()?;
+        conn.damage_query_version(1, 1)?.reply()?;
+
+        let formats = conn.render_query_pict_formats()?.reply()?;
+        let opacity_atom = conn
+            .intern_atom(false, b"_NET_WM_WINDOW_OPACITY")?
+            .reply()?
+            .atom;
+        let screen = &conn.setup().roots[screen_num];
+        let (root, root_depth, root_visual) = (screen.root, screen.root_depth, screen.root_visual);
+
+        let border_color = parse_color(&config.border_color)
+            .with_context(|| format!("Invalid preview.border_color {:?}", config.border_color))?;
+
+        Ok(Self {
+            conn,
+            root,
+            root_depth,
+            root_visual,
+            formats,
+            opacity_atom,
+            config,
+            border_color: render_color(border_color),
+            thumbnails: HashMap::new(),
+        })
+    }
+
+    fn run(
+        mut self,
+        state: &Mutex<CycleState>,
+        commands: &CommandSender,
+        stop: &AtomicBool,
+    ) -> Result<()> {
+        let mut active = None;
+
+        while !stop.load(Ordering::Relaxed) {
+            while let Some(event) = self.conn.poll_for_event()? {
+                self.handle_event(event, commands);
+            }
+
+            let (windows, current) = {
+                let state = state.lock().unwrap();
+                let windows = state.get_windows().to_vec();
+                let current = windows
+                    .get(state.get_current_index())
+                    .map(|w| w.id.as_xid());
+                (windows, current)
+            };
+
+            self.sync(&windows)?;
+            if current != active {
+                // Both borders change color
+                for source in [active, current].into_iter().flatten() {
+                    self.mark_dirty(source);
+                }
+                // Window managers may stack a newly focused client over us
+                active = current;
+                self.raise()?;
+            }
+            self.draw(active)?;
+            self.conn.flush()?;
+
+            std::thread::sleep(FRAME_INTERVAL);
+        }
+
+        Ok(())
+    }
+
+    fn format_for_visual(&self, visual: Visualid) -> Option<Pictformat> {
+        self.formats
+            .screens
+            .iter()
+            .flat_map(|screen| &screen.depths)
+            .flat_map(|depth| &depth.visuals)
+            .find(|v| v.visual == visual)
+            .map(|v| v.format)
+    }
+
+    /// Create thumbnails for new clients, drop those of clients that are gone
+    /// and move the rest to where they now belong
+    fn sync(&mut self, windows: &[EveWindow]) -> Result<()> {
+        let stale: Vec<Window> = self
+            .thumbnails
+            .keys()
+            .copied()
+            .filter(|&source| !windows.iter().any(|w| w.id.as_xid() == source))
+            .collect();
+        for source in stale {
+            self.remove(source)?;
+        }
+
+        let positions = thumbnail_positions(&self.config, windows);
+        for (window, position) in windows.iter().zip(positions) {
+            let source = window.id.as_xid();
+
+            match self.thumbnails.get_mut(&source) {
+                Some(thumbnail) if thumbnail.position != position => {
+                    thumbnail.position = position;
+                    let values = ConfigureWindowAux::new()
+                        .x(i32::from(position.0))
+                        .y(i32::from(position.1));
+                    self.conn.configure_window(thumbnail.window, &values)?;
+                }
+                Some(_) => {}
+                None => {
+                    let thumbnail = self.create(source, &window.title, position)?;
+                    self.thumbnails.insert(source, thumbnail);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn create(&self, source: Window, character: &str, position: (i16, i16)) -> Result<Thumbnail> {
+        // Keep the client's contents off-screen so they survive being covered
+        self.conn
+            .composite_redirect_window(source, Redirect::AUTOMATIC)?;
+        let events = ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY);
+        self.conn.change_window_attributes(source, &events)?;
+        let damage = self.conn.generate_id()?;
+        self.conn
+            .damage_create(damage, source, ReportLevel::NON_EMPTY)?;
+
+        let window = self.conn.generate_id()?;
+        // Override-redirect: placed exactly where configured, undecorated and
+        // left out of the window manager's client list
+        let values = CreateWindowAux::new()
+            .background_pixel(0)
+            .override_redirect(1)
+            .event_mask(EventMask::BUTTON_PRESS | EventMask::EXPOSURE);
+        self.conn.create_window(
+            self.root_depth,
+            window,
+            self.root,
+            position.0,
+            position.1,
+            self.config.width,
+            self.config.height,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            self.root_visual,
+            &values,
+        )?;
+        self.conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_NAME,
+            AtomEnum::STRING,
+            format!("Nicotine Preview - {}", character).as_bytes(),
+        )?;
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            self.opacity_atom,
+            AtomEnum::CARDINAL,
+            &[opacity_value(self.config.opacity)],
+        )?;
+
+        let format = self
+            .format_for_visual(self.root_visual)
+            .context("No picture format for the root visual")?;
+        let picture = self.conn.generate_id()?;
+        self.conn
+            .render_create_picture(picture, window, format, &CreatePictureAux::new())?;
+        self.conn.map_window(window)?;
+
+        Ok(Thumbnail {
+            window,
+            picture,
+            position,
+            source: None,
+            size: (0, 0),
+            damage,
+            dirty: true,
+            bind_pending: true,
+        })
+    }
+
+    fn remove(&mut self, source: Window) -> Result<()> {
+        let Some(mut thumbnail) = self.thumbnails.remove(&source) else {
+            return Ok(());
+        };
+        self.release_source(&mut thumbnail)?;
+        self.conn.render_free_picture(thumbnail.picture)?;
+        self.conn.destroy_window(thumbnail.window)?;
+        // The client may already be gone, in which case these errors are ignored
+        self.conn.damage_destroy(thumbnail.damage)?;
+        self.conn
+            .composite_unredirect_window(source, Redirect::AUTOMATIC)?;
+        Ok(())
+    }
+
+    /// Name the client's current pixmap and wrap it in a picture scaled to the
+    /// thumbnail, along with the client's size. Fails while the client is
+    /// unmapped (e.g. minimized).
+    fn bind_source(&self, source: Window) -> Result<(Pixmap, Picture, (u16, u16))> {
+        let attributes = self.conn.get_window_attributes(source)?.reply()?;
+        if attributes.map_state != MapState::VIEWABLE {
+            anyhow::bail!("Window {} is not viewable", source);
+        }
+        let geometry = self.conn.get_geometry(source)?.reply()?;
+        let format = self
+            .format_for_visual(attributes.visual)
+            .context("No picture format for the client's visual")?;
+
+        let pixmap = self.conn.generate_id()?;
+        self.conn
+            .composite_name_window_pixmap(source, pixmap)?
+            .check()?;
+
+        let picture = self.conn.generate_id()?;
+        self.conn
+            .render_create_picture(picture, pixmap, format, &CreatePictureAux::new())?;
+        self.conn.render_set_picture_transform(
+            picture,
+            scale_transform(
+                (geometry.width, geometry.height),
+                content_size(&self.config),
+            ),
+        )?;
+        self.conn
+            .render_set_picture_filter(picture, b"bilinear", &[])?;
+
+        Ok((pixmap, picture, (geometry.width, geometry.height)))
+    }
+
+    /// Forget the client's pixmap. Needed whenever the client is resized,
+    /// unmapped or mapped again.
+    fn release_source(&self, thumbnail: &mut Thumbnail) -> Result<()> {
+        if let Some((pixmap, picture)) = thumbnail.source.take() {
+            self.conn.render_free_picture(picture)?;
+            self.conn.free_pixmap(pixmap)?;
+        }
+        Ok(())
+    }
+
+    /// Drop the client's pixmap after it changed, and whether to name it
+    /// again on the next draw
+    fn invalidate(&mut self, source: Window, rebind: bool) {
+        if let Some(mut thumbnail) = self.thumbnails.remove(&source) {
+            let _ = self.release_source(&mut thumbnail);
+            thumbnail.bind_pending = rebind;
+            thumbnail.dirty = true;
+            self.thumbnails.insert(source, thumbnail);
+        }
+    }
+
+    fn mark_dirty(&mut self, source: Window) {
+        if let Some(thumbnail) = self.thumbnails.get_mut(&source) {
+            thumbnail.dirty = true;
+        }
+    }
+
+    fn raise(&self) -> Result<()> {
+        let values = ConfigureWindowAux::new().stack_mode(StackMode::ABOVE);
+        for thumbnail in self.thumbnails.values() {
+            self.conn.configure_window(thumbnail.window, &values)?;
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, active: Option<Window>) -> Result<()> {
+        let (width, height) = (self.config.width, self.config.height);
+        let border = self.config.border_width;
+        let (content_width, content_height) = content_size(&self.config);
+        let black = render_color([0, 0, 0]);
+
+        let sources: Vec<Window> = self
+            .thumbnails
+            .iter()
+            .filter(|(_, t)| t.dirty)
+            .map(|(&source, _)| source)
+            .collect();
+        for source in sources {
+            if self.thumbnails[&source].bind_pending {
+                let bound = self.bind_source(source).ok();
+                let thumbnail = self.thumbnails.get_mut(&source).unwrap();
+                thumbnail.source = bound.map(|(pixmap, picture, size)| {
+                    thumbnail.size = size;
+                    (pixmap, picture)
+                });
+                thumbnail.bind_pending = false;
+            }
+            let thumbnail = self.thumbnails.get_mut(&source).unwrap();
+            thumbnail.dirty = false;
+            let thumbnail = &*thumbnail;
+
+            let color = if Some(source) == active {
+                self.border_color
+            } else {
+                black
+            };
+            if border > 0 {
+                self.conn.render_fill_rectangles(
+                    PictOp::SRC,
+                    thumbnail.picture,
+                    color,
+                    &border_rects(width, height, border),
+                )?;
+            }
+
+            let offset = border.min(width / 2).min(height / 2) as i16;
+            match thumbnail.source {
+                Some((_, picture)) => {
+                    self.conn.render_composite(
+                        PictOp::SRC,
+                        picture,
+                        x11rb::NONE,
+                        thumbnail.picture,
+                        0,
+                        0,
+                        0,
+                        0,
+                        offset,
+                        offset,
+                        content_width,
+                        content_height,
+                    )?;
+                }
+                None => {
+                    let content = Rectangle {
+                        x: offset,
+                        y: offset,
+                        width: content_width,
+                        height: content_height,
+                    };
+                    self.conn.render_fill_rectangles(
+                        PictOp::SRC,
+                        thumbnail.picture,
+                        black,
+                        &[content],
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: Event, commands: &CommandSender) {
+        match event {
+            Event::ButtonPress(event) if event.detail == BUTTON_PRIMARY => {
+                let source = self
+                    .thumbnails
+                    .iter()
+                    .find(|(_, t)| t.window == event.event)
+                    .map(|(&source, _)| source);
+                if let Some(source) = source {
+                    commands.send(Command::Focus(WindowId::from(source)));
+                }
+            }
+            Event::DamageNotify(event) => {
+                // Re-arm the report, which stays quiet until the damage is cleared
+                let _ = self
+                    .conn
+                    .damage_subtract(event.damage, x11rb::NONE, x11rb::NONE);
+                self.mark_dirty(event.drawable);
+            }
+            Event::Expose(ExposeEvent { window, .. }) => {
+                let source = self
+                    .thumbnails
+                    .iter()
+                    .find(|(_, t)| t.window == window)
+                    .map(|(&source, _)| source);
+                if let Some(source) = source {
+                    self.mark_dirty(source);
+                }
+            }
+            Event::ConfigureNotify(ConfigureNotifyEvent {
+                window,
+                width,
+                height,
+                ..
+            }) => {
+                let resized = self
+                    .thumbnails
+                    .get(&window)
+                    .is_some_and(|t| t.size != (width, height));
+                if resized {
+                    self.invalidate(window, true);
+                }
+            }
+            Event::MapNotify(MapNotifyEvent { window, .. }) => {
+                self.invalidate(window, true);
+            }
+            Event::UnmapNotify(UnmapNotifyEvent { window, .. }) => {
+                self.invalidate(window, false);
+            }
+            // Requests on clients that have just closed fail; the next sync
+            // removes their thumbnails
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preview_config() -> PreviewConfig {
+        crate::test_support::test_config().preview
+    }
+
+    #[test]
+    fn test_thumbnails_form_a_row_unless_placed() {
+        let mut config = preview_config();
+        config.positions.insert("Beta".to_string(), [500, 20]);
+        let windows: Vec<EveWindow> = ["Alpha", "Beta", "Gamma"]
+            .iter()
+            .enumerate()
+            .map(|(i, title)| EveWindow {
+                id: WindowId(i as u64 + 1),
+                title: title.to_string(),
+            })
+            .collect();
+
+        assert_eq!(
+            thumbnail_positions(&config, &windows),
+            // Gamma takes the spot after Alpha: 10 + 256 + 8
+            vec![(10, 400), (500, 20), (274, 400)]
+        );
+    }
+
+    #[test]
+    fn test_scale_transform() {
+        let transform = scale_transform((1024, 576), (256, 144));
+        assert_eq!(transform.matrix11, 4 << 16);
+        assert_eq!(transform.matrix22, 4 << 16);
+        assert_eq!(transform.matrix33, 1 << 16);
+    }
+
+    #[test]
+    fn test_border_rects_leave_content_size() {
+        let config = preview_config();
+        assert_eq!(content_size(&config), (252, 140));
+
+        let [top, bottom, left, right] = border_rects(256, 144, 2);
+        assert_eq!((top.y, top.height), (0, 2));
+        assert_eq!((bottom.y, bottom.height), (142, 2));
+        assert_eq!((left.x, left.y, left.width, left.height), (0, 2, 2, 140));
+        assert_eq!(right.x, 254);
+    }
+
+    #[test]
+    fn test_opacity_value() {
+        assert_eq!(opacity_value(1.0), u32::MAX);
+        assert_eq!(opacity_value(0.0), 0);
+        assert_eq!(opacity_value(2.0), u32::MAX);
+    }
+}
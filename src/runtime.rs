@@ -0,0 +1,168 @@
+use anyhow::{Context, Result};
+use nix::unistd::getuid;
+use std::ffi::OsString;
+use std::fs::{self, DirBuilder};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// Per-user directory holding the daemon socket, PID file and cycle lock.
+///
+/// `$XDG_RUNTIME_DIR/nicotine` when the session provides one, otherwise
+/// `/tmp/nicotine-<uid>` so users sharing a machine never collide.
+pub fn runtime_dir() -> PathBuf {
+    resolve_runtime_dir(std::env::var_os("XDG_RUNTIME_DIR"), getuid().as_raw())
+}
+
+pub fn socket_path() -> PathBuf {
+    runtime_dir().join("nicotine.sock")
+}
+
+pub fn lock_path() -> PathBuf {
+    runtime_dir().join("cycle.lock")
+}
+
+pub fn pid_path() -> PathBuf {
+    runtime_dir().join("nicotine.pid")
+}
+
+/// Record this process as the running daemon
+pub fn write_pid_file() -> Result<()> {
+    ensure_runtime_dir()?;
+    fs::write(pid_path(), std::process::id().to_string()).context("Failed to write PID file")
+}
+
+/// PID of the daemon recorded by `write_pid_file`, if any
+pub fn read_pid_file() -> Option<i32> {
+    fs::read_to_string(pid_path())
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+}
+
+/// Create the runtime directory if needed and make sure nobody else can use it
+pub fn ensure_runtime_dir() -> Result<PathBuf> {
+    let dir = runtime_dir();
+    ensure_private_dir(&dir, getuid().as_raw())?;
+    Ok(dir)
+}
+
+fn resolve_runtime_dir(xdg_runtime_dir: Option<OsString>, uid: u32) -> PathBuf {
+    match xdg_runtime_dir.map(PathBuf::from) {
+        // The spec requires an absolute path; ignore anything else
+        Some(dir) if dir.is_absolute() => dir.join("nicotine"),
+        _ => PathBuf::from(format!("/tmp/nicotine-{}", uid)),
+    }
+}
+
+/// Create `dir` with mode 0700, or verify an existing one is a real directory owned by `uid`.
+/// In the shared /tmp fallback another user could have created the path first, so a
+/// symlink or foreign owner is refused rather than followed.
+fn ensure_private_dir(dir: &Path, uid: u32) -> Result<()> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to create {}", dir.display()));
+        }
+    }
+
+    let metadata = fs::symlink_metadata(dir)
+        .with_context(|| format!("Failed to inspect {}", dir.display()))?;
+
+    if !metadata.is_dir() {
+        anyhow::bail!("{} exists but is not a directory", dir.display());
+    }
+    if metadata.uid() != uid {
+        anyhow::bail!(
+            "{} is owned by uid {}, refusing to use it",
+            dir.display(),
+            metadata.uid()
+        );
+    }
+    if metadata.mode() & 0o077 != 0 {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to restrict permissions on {}", dir.display()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("nicotine-test-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_uses_xdg_runtime_dir() {
+        assert_eq!(
+            resolve_runtime_dir(Some("/run/user/1000".into()), 1000),
+            PathBuf::from("/run/user/1000/nicotine")
+        );
+    }
+
+    #[test]
+    fn test_falls_back_to_per_user_tmp() {
+        assert_eq!(
+            resolve_runtime_dir(None, 1000),
+            PathBuf::from("/tmp/nicotine-1000")
+        );
+        assert_eq!(
+            resolve_runtime_dir(Some("relative/dir".into()), 1000),
+            PathBuf::from("/tmp/nicotine-1000")
+        );
+    }
+
+    #[test]
+    fn test_creates_private_dir() {
+        let dir = temp_path("create");
+        let _ = fs::remove_dir_all(&dir);
+
+        ensure_private_dir(&dir, getuid().as_raw()).unwrap();
+        let mode = fs::metadata(&dir).unwrap().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_tightens_existing_dir() {
+        let dir = temp_path("tighten");
+        let _ = fs::remove_dir_all(&dir);
+        DirBuilder::new().mode(0o755).create(&dir).unwrap();
+
+        ensure_private_dir(&dir, getuid().as_raw()).unwrap();
+        let mode = fs::metadata(&dir).unwrap().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_foreign_owner() {
+        let dir = temp_path("foreign");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+
+        let other_uid = getuid().as_raw().wrapping_add(1);
+        assert!(ensure_private_dir(&dir, other_uid).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_symlink() {
+        let target = temp_path("symlink-target");
+        let link = temp_path("symlink");
+        let _ = fs::remove_dir_all(&target);
+        let _ = fs::remove_file(&link);
+        fs::create_dir(&target).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        assert!(ensure_private_dir(&link, getuid().as_raw()).is_err());
+
+        fs::remove_file(&link).unwrap();
+        fs::remove_dir_all(&target).unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+use crate::config::Config;
+use crate::i3ipc::{read_message, write_message};
+use std::fs;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+/// A config with every optional setting at its default
+pub fn test_config() -> Config {
+    toml::from_str(
+        "display_width = 1920
+display_height = 1080
+panel_height = 0
+eve_width = 1037
+eve_height = 1080
+overlay_x = 10.0
+overlay_y = 10.0",
+    )
+    .unwrap()
+}
+
+/// How clients of a stand-in compositor socket connect
+pub enum Connections {
+    /// One connection carries every request (i3, sway)
+    Shared,
+    /// A new connection per request (Hyprland, niri)
+    PerRequest,
+}
+
+/// Stand-in compositor socket at `path`: `exchange` reads one request off the
+/// stream, writes the next canned reply and returns what it received. Requests
+/// are reported as they arrive; the receiver ends once every reply went out.
+pub fn serve_socket<R, T>(
+    path: &Path,
+    replies: Vec<R>,
+    connections: Connections,
+    exchange: fn(&mut UnixStream, R) -> T,
+) -> Receiver<T>
+where
+    R: Send + 'static,
+    T: Send + 'static,
+{
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path).unwrap();
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        let mut shared = None;
+        for reply in replies {
+            let request = match connections {
+                Connections::Shared => {
+                    let stream = shared.get_or_insert_with(|| listener.accept().unwrap().0);
+                    exchange(stream, reply)
+                }
+                Connections::PerRequest => exchange(&mut listener.accept().unwrap().0, reply),
+            };
+            if tx.send(request).is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+/// Stand-in i3/sway IPC socket: answers each request with the next canned
+/// reply and reports every request it received as `(type, payload)`
+pub fn serve(name: &str, replies: Vec<(u32, String)>) -> (PathBuf, Receiver<(u32, String)>) {
+    let path = std::env::temp_dir().join(format!(
+        "nicotine-test-{}-{}.sock",
+        name,
+        std::process::id()
+    ));
+    let requests = serve_socket(
+        &path,
+        replies,
+        Connections::Shared,
+        |stream, (reply_type, reply)| {
+            let (message_type, payload) = read_message(stream).unwrap();
+            write_message(stream, reply_type, reply.as_bytes()).unwrap();
+            (message_type, String::from_utf8(payload).unwrap())
+        },
+    );
+
+    (path, requests)
+}
@@ -1,115 +1,171 @@
 use crate::config::Config;
-use crate::window_manager::{EveWindow, WindowManager};
+use crate::gnome_shell::{self, ShellProxyBlocking};
+use crate::i3ipc::I3Ipc;
+use crate::kwin_scripting::KWinScripting;
+use crate::window_manager::{Backend, Capabilities, EveWindow, WindowId, WindowManager};
 use anyhow::{Context, Result};
+use serde::Deserialize;
 use serde_json::Value;
-use std::process::Command;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
 
 // ============================================================================
-// KDE Plasma / KWin Backend (via wmctrl through XWayland)
+// KDE Plasma / KWin Backend (via KWin scripting over D-Bus)
 // ============================================================================
 
-pub struct KWinManager;
+pub struct KWinManager {
+    scripting: KWinScripting,
+    ids: Mutex<KWinWindowIds>,
+}
 
-impl KWinManager {
-    pub fn new() -> Result<Self> {
-        Command::new("wmctrl")
-            .arg("-m")
-            .output()
-            .context("wmctrl not found. Install wmctrl package")?;
+/// A window as listed by the enumeration script
+#[derive(Debug, Deserialize)]
+struct KWinWindow {
+    id: String,
+    caption: String,
+}
 
-        Ok(Self)
+/// KWin identifies windows by UUID; hand out a stable sequential `WindowId` for each
+#[derive(Default)]
+struct KWinWindowIds {
+    by_uuid: HashMap<String, WindowId>,
+    uuids: HashMap<WindowId, String>,
+    /// Last id handed out; ids are never reused, even once their window is gone
+    last: u64,
+}
+
+impl KWinWindowIds {
+    fn id(&mut self, uuid: &str) -> WindowId {
+        if let Some(&id) = self.by_uuid.get(uuid) {
+            return id;
+        }
+        self.last += 1;
+        let id = WindowId(self.last);
+        self.by_uuid.insert(uuid.to_string(), id);
+        self.uuids.insert(id, uuid.to_string());
+        id
     }
 
-    fn get_all_windows(&self) -> Result<Vec<(String, String)>> {
-        let output = Command::new("wmctrl")
-            .arg("-l")
-            .output()
-            .context("Failed to execute wmctrl")?;
+    /// Forget every window not in `listed`
+    fn retain(&mut self, listed: &[KWinWindow]) {
+        self.by_uuid
+            .retain(|uuid, _| listed.iter().any(|window| &window.id == uuid));
+        self.uuids
+            .retain(|_, uuid| listed.iter().any(|window| &window.id == uuid));
+    }
 
-        if !output.status.success() {
-            anyhow::bail!("wmctrl failed: {}", String::from_utf8_lossy(&output.stderr));
-        }
+    fn uuid(&self, id: WindowId) -> Result<&str> {
+        self.uuids
+            .get(&id)
+            .map(String::as_str)
+            .with_context(|| format!("Unknown KWin window {}", id))
+    }
+}
 
-        let mut windows = Vec::new();
-        let lines = String::from_utf8_lossy(&output.stdout);
-
-        for line in lines.lines() {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 4 {
-                let window_id = parts[0];
-                let title = parts[3..].join(" ");
-                windows.push((window_id.to_string(), title));
-            }
-        }
+/// Enumerate normal windows as `[{id, caption}]`
+const KWIN_LIST_WINDOWS: &str = "reply(windows.filter(w => w.normalWindow)
+    .map(w => ({ id: String(w.internalId), caption: w.caption })));";
 
-        Ok(windows)
+const KWIN_ACTIVE_WINDOW: &str = "const w = activeWindow();
+reply(w ? String(w.internalId) : null);";
+
+/// Report window changes for as long as the script stays loaded. KWin 6
+/// renamed the client signals to window ones.
+const KWIN_WATCH_WINDOWS: &str = "const added = workspace.windowAdded || workspace.clientAdded;
+const removed = workspace.windowRemoved || workspace.clientRemoved;
+const activated = workspace.windowActivated || workspace.clientActivated;
+
+// EVE only gets its \"EVE - <character>\" title once a character logs in
+function watchCaption(w) {
+    w.captionChanged.connect(changed);
+}
+
+(workspace.windowList ? workspace.windowList() : workspace.clientList()).forEach(watchCaption);
+added.connect(w => { watchCaption(w); changed(); });
+removed.connect(changed);
+activated.connect(changed);";
+
+impl KWinManager {
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_scripting(KWinScripting::session()?))
     }
 
-    fn get_window_title_by_id(&self, hex_id: &str) -> Option<String> {
-        let output = Command::new("wmctrl").arg("-l").output().ok()?;
-        if !output.status.success() {
-            return None;
+    pub fn with_scripting(scripting: KWinScripting) -> Self {
+        Self {
+            scripting,
+            ids: Mutex::new(KWinWindowIds::default()),
         }
+    }
 
-        let lines = String::from_utf8_lossy(&output.stdout);
-        for line in lines.lines() {
-            if line.starts_with(hex_id) {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 4 {
-                    return Some(parts[3..].join(" "));
-                }
-            }
+    fn get_all_windows(&self) -> Result<Vec<(WindowId, String)>> {
+        let windows: Vec<KWinWindow> = self.scripting.run(KWIN_LIST_WINDOWS)?;
+        let mut ids = self.ids.lock().unwrap();
+        ids.retain(&windows);
+        Ok(windows
+            .into_iter()
+            .map(|window| (ids.id(&window.id), window.caption))
+            .collect())
+    }
+
+    /// JavaScript string literal for the window's UUID
+    fn uuid_literal(&self, window_id: WindowId) -> Result<String> {
+        let ids = self.ids.lock().unwrap();
+        Ok(serde_json::to_string(ids.uuid(window_id)?)?)
+    }
+
+    /// Run `action` with `w` bound to the window, failing if it has gone away
+    fn with_window(&self, window_id: WindowId, action: &str) -> Result<()> {
+        let script = format!(
+            "const w = findWindow({});\nif (w) {{ {} }}\nreply(!!w);",
+            self.uuid_literal(window_id)?,
+            action
+        );
+        if !self.scripting.run::<bool>(&script)? {
+            anyhow::bail!("Window {} no longer exists", window_id);
         }
-        None
+        Ok(())
     }
 }
 
 impl WindowManager for KWinManager {
+    fn backend(&self) -> Backend {
+        Backend::Kde
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            move_windows: true,
+            resize: true,
+            minimize: true,
+            events: true,
+            thumbnails: false,
+        }
+    }
+
     fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
         let windows = self.get_all_windows()?;
         let mut eve_windows = Vec::new();
 
-        for (id_str, title) in windows {
+        for (id, title) in windows {
             if title.starts_with("EVE - ") && !title.contains("Launcher") {
-                // Parse hex window ID (e.g., "0x06e00008") to u32
-                let id = if let Some(hex) = id_str.strip_prefix("0x") {
-                    u32::from_str_radix(hex, 16).unwrap_or(0)
-                } else {
-                    id_str.parse::<u32>().unwrap_or(0)
-                };
-
-                if id != 0 {
-                    eve_windows.push(EveWindow {
-                        id,
-                        title: title.trim_start_matches("EVE - ").to_string(),
-                    });
-                }
+                eve_windows.push(EveWindow {
+                    id,
+                    title: title.trim_start_matches("EVE - ").to_string(),
+                });
             }
         }
 
         Ok(eve_windows)
     }
 
-    fn activate_window(&self, window_id: u32) -> Result<()> {
-        let hex_id = format!("0x{:08x}", window_id);
-
-        if let Some(title) = self.get_window_title_by_id(&hex_id) {
-            if Command::new("kdotool")
-                .args(["search", "--name", &title, "windowactivate"])
-                .output()
-                .map(|o| o.status.success())
-                .unwrap_or(false)
-            {
-                return Ok(());
-            }
-        }
-
-        Command::new("wmctrl")
-            .args(["-i", "-a", &hex_id])
-            .output()
-            .context("Failed to activate window")?;
-
-        Ok(())
+    fn activate_window(&self, window_id: WindowId) -> Result<()> {
+        self.with_window(window_id, "activate(w);")
+            .context("Failed to activate window")
     }
 
     fn stack_windows(&self, windows: &[EveWindow], config: &Config) -> Result<()> {
@@ -118,112 +174,255 @@ impl WindowManager for KWinManager {
         let width = config.eve_width;
         let height = config.display_height - config.panel_height;
 
+        // Set every window's geometry from a single script
+        let mut script = String::new();
         for window in windows {
-            // Convert u32 to hex format for wmctrl
-            let hex_id = format!("0x{:08x}", window.id);
-
-            // Move and resize window using wmctrl
-            Command::new("wmctrl")
-                .arg("-i")
-                .arg("-r")
-                .arg(&hex_id)
-                .arg("-e")
-                .arg(format!("0,{},{},{},{}", x, y, width, height))
-                .output()?;
+            script.push_str(&format!(
+                "{{ const w = findWindow({}); if (w) w.frameGeometry = {{ x: {}, y: {}, width: {}, height: {} }}; }}\n",
+                self.uuid_literal(window.id)?,
+                x,
+                y,
+                width,
+                height
+            ));
         }
+        script.push_str("reply(true);");
 
+        self.scripting.run::<bool>(&script)?;
         Ok(())
     }
 
-    fn get_active_window(&self) -> Result<u32> {
-        // Use xdotool to get active window (works through XWayland)
-        let output = Command::new("xdotool")
-            .arg("getactivewindow")
-            .output()
-            .context("Failed to get active window")?;
+    fn get_active_window(&self) -> Result<WindowId> {
+        let uuid: Option<String> = self.scripting.run(KWIN_ACTIVE_WINDOW)?;
+        let uuid = uuid.context("No active window found")?;
+        Ok(self.ids.lock().unwrap().id(&uuid))
+    }
 
-        let window_id = String::from_utf8_lossy(&output.stdout)
-            .trim()
-            .parse::<u32>()
-            .context("Failed to parse active window ID")?;
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowId>> {
+        let windows = self.get_all_windows()?;
 
-        Ok(window_id)
+        Ok(windows
+            .into_iter()
+            .find(|(_, window_title)| window_title == title)
+            .map(|(id, _)| id))
     }
 
-    fn find_window_by_title(&self, title: &str) -> Result<Option<u32>> {
-        let windows = self.get_all_windows()?;
+    fn move_window(&self, window_id: WindowId, x: i32, y: i32) -> Result<()> {
+        self.with_window(
+            window_id,
+            &format!(
+                "w.frameGeometry = {{ x: {}, y: {}, width: w.frameGeometry.width, height: w.frameGeometry.height }};",
+                x, y
+            ),
+        )
+    }
 
-        for (id_str, window_title) in windows {
-            if window_title == title {
-                // Parse hex window ID (e.g., "0x06e00008") to u32
-                let id = if let Some(hex) = id_str.strip_prefix("0x") {
-                    u32::from_str_radix(hex, 16).unwrap_or(0)
-                } else {
-                    id_str.parse::<u32>().unwrap_or(0)
-                };
-
-                if id != 0 {
-                    return Ok(Some(id));
-                }
-            }
+    fn minimize_window(&self, window_id: WindowId) -> Result<()> {
+        self.with_window(window_id, "w.minimized = true;")
+            .context("Failed to minimize window")
+    }
+
+    fn restore_window(&self, window_id: WindowId) -> Result<()> {
+        self.with_window(window_id, "w.minimized = false;")
+            .context("Failed to restore window")
+    }
+
+    fn watch_windows(&self, notify: Sender<()>) -> Result<bool> {
+        self.scripting.watch(KWIN_WATCH_WINDOWS, notify)?;
+        Ok(true)
+    }
+}
+
+// ============================================================================
+// GNOME Shell Backend (via the companion extension over D-Bus)
+// ============================================================================
+
+pub struct GnomeManager {
+    shell: ShellProxyBlocking<'static>,
+}
+
+impl GnomeManager {
+    pub fn new() -> Result<Self> {
+        let conn = zbus::blocking::Connection::session()
+            .context("Failed to connect to the session D-Bus")?;
+        let shell = ShellProxyBlocking::new(&conn)?;
+
+        // Fail early with instructions rather than on the first cycle
+        shell.list_windows().with_context(|| {
+            format!(
+                "The Nicotine GNOME Shell extension isn't running. Install it with \
+                 `nicotine install-gnome-extension`, then enable it with \
+                 `gnome-extensions enable {}`",
+                gnome_shell::EXTENSION_UUID
+            )
+        })?;
+
+        Ok(Self::with_proxy(shell))
+    }
+
+    pub fn with_proxy(shell: ShellProxyBlocking<'static>) -> Self {
+        Self { shell }
+    }
+}
+
+impl WindowManager for GnomeManager {
+    fn backend(&self) -> Backend {
+        Backend::Gnome
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            move_windows: true,
+            resize: true,
+            minimize: true,
+            events: true,
+            thumbnails: false,
         }
+    }
 
-        Ok(None)
+    fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
+        let mut windows: Vec<EveWindow> = self
+            .shell
+            .list_windows()?
+            .into_iter()
+            .filter(|(_, title)| title.starts_with("EVE - ") && !title.contains("Launcher"))
+            .map(|(id, title)| EveWindow {
+                id: WindowId(id),
+                title: title.trim_start_matches("EVE - ").to_string(),
+            })
+            .collect();
+        // Mutter ids only ever increase, so this is creation order whatever
+        // order the extension reports; it must not follow the stacking order
+        windows.sort_by_key(|w| w.id.0);
+        Ok(windows)
     }
 
-    fn minimize_window(&self, window_id: u32) -> Result<()> {
-        let hex_id = format!("0x{:08x}", window_id);
-        Command::new("xdotool")
-            .args(["windowminimize", &hex_id])
-            .output()
-            .context("Failed to minimize window")?;
-        Ok(())
+    fn activate_window(&self, window_id: WindowId) -> Result<()> {
+        self.shell
+            .activate(window_id.0)
+            .context("Failed to activate window")
     }
 
-    fn restore_window(&self, window_id: u32) -> Result<()> {
-        let hex_id = format!("0x{:08x}", window_id);
-        // wmctrl -i -a activates and restores from minimized state
-        Command::new("wmctrl")
-            .args(["-i", "-a", &hex_id])
-            .output()
-            .context("Failed to restore window")?;
+    fn stack_windows(&self, windows: &[EveWindow], config: &Config) -> Result<()> {
+        let x = ((config.display_width - config.eve_width) / 2) as i32;
+        let y = 0;
+        let width = config.eve_width as i32;
+        let height = (config.display_height - config.panel_height) as i32;
+
+        for window in windows {
+            self.shell
+                .move_resize(window.id.0, x, y, width, height)
+                .context("Failed to stack window")?;
+        }
         Ok(())
     }
+
+    fn get_active_window(&self) -> Result<WindowId> {
+        Ok(WindowId(
+            self.shell
+                .active_window()
+                .context("No active window found")?,
+        ))
+    }
+
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowId>> {
+        Ok(self
+            .shell
+            .list_windows()?
+            .into_iter()
+            .find(|(_, window_title)| window_title == title)
+            .map(|(id, _)| WindowId(id)))
+    }
+
+    fn move_window(&self, window_id: WindowId, x: i32, y: i32) -> Result<()> {
+        // The extension only moves and resizes together, so keep the current size
+        let (_, _, width, height) = self
+            .shell
+            .frame_rect(window_id.0)
+            .context("Failed to get window size")?;
+        self.shell
+            .move_resize(window_id.0, x, y, width, height)
+            .context("Failed to move window")
+    }
+
+    fn window_position(&self, window_id: WindowId) -> Result<Option<(i32, i32)>> {
+        let (x, y, _, _) = self
+            .shell
+            .frame_rect(window_id.0)
+            .context("Failed to get window position")?;
+        Ok(Some((x, y)))
+    }
+
+    fn minimize_window(&self, window_id: WindowId) -> Result<()> {
+        self.shell
+            .minimize(window_id.0)
+            .context("Failed to minimize window")
+    }
+
+    fn restore_window(&self, window_id: WindowId) -> Result<()> {
+        self.shell
+            .unminimize(window_id.0)
+            .context("Failed to restore window")
+    }
+
+    fn watch_windows(&self, notify: Sender<()>) -> Result<bool> {
+        let changes = self.shell.receive_windows_changed()?;
+        std::thread::spawn(move || {
+            for _ in changes {
+                if notify.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(true)
+    }
 }
 
 // ============================================================================
-// Sway Backend (via swaymsg)
+// Sway Backend (via the sway IPC socket)
 // ============================================================================
 
-pub struct SwayManager;
+pub struct SwayManager {
+    ipc: I3Ipc,
+}
+
+/// Workspace that holds hidden scratchpad windows in sway and i3
+const SCRATCHPAD_WORKSPACE: &str = "__i3_scratch";
+
+/// Name of the workspace containing container `id`, if it's in the tree
+fn workspace_of(node: &Value, id: u64, workspace: Option<&str>) -> Option<String> {
+    let workspace = if node.get("type").and_then(|t| t.as_str()) == Some("workspace") {
+        node.get("name").and_then(|n| n.as_str())
+    } else {
+        workspace
+    };
+
+    if node.get("id").and_then(|i| i.as_u64()) == Some(id) {
+        return workspace.map(|name| name.to_string());
+    }
+
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node.get(key).and_then(|n| n.as_array()))
+        .flatten()
+        .find_map(|child| workspace_of(child, id, workspace))
+}
 
 impl SwayManager {
     pub fn new() -> Result<Self> {
-        // Verify swaymsg is available
-        Command::new("swaymsg")
-            .arg("--version")
-            .output()
-            .context("swaymsg not found. Make sure you're running Sway")?;
+        let socket = std::env::var_os("SWAYSOCK")
+            .context("SWAYSOCK not set. Make sure you're running Sway")?;
+        Self::with_socket(socket)
+    }
 
-        Ok(Self)
+    pub fn with_socket(path: impl Into<PathBuf>) -> Result<Self> {
+        Ok(Self {
+            ipc: I3Ipc::connect(path)?,
+        })
     }
 
     fn get_all_windows(&self) -> Result<Vec<Value>> {
-        let output = Command::new("swaymsg")
-            .arg("-t")
-            .arg("get_tree")
-            .output()
-            .context("Failed to execute swaymsg")?;
-
-        if !output.status.success() {
-            anyhow::bail!(
-                "swaymsg failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            );
-        }
-
-        let tree: Value =
-            serde_json::from_slice(&output.stdout).context("Failed to parse swaymsg output")?;
+        let tree = self.ipc.get_tree()?;
 
         let mut windows = Vec::new();
         Self::extract_windows(&tree, &mut windows);
@@ -266,12 +465,33 @@ impl SwayManager {
             .map(|s| s.to_string())
     }
 
-    fn get_window_id(window: &Value) -> Option<u32> {
-        window.get("id").and_then(|i| i.as_u64()).map(|i| i as u32)
+    fn get_window_id(window: &Value) -> Option<WindowId> {
+        window.get("id").and_then(|i| i.as_u64()).map(WindowId)
+    }
+
+    /// Whether the window is hidden in the scratchpad. `move scratchpad` and
+    /// `scratchpad show` both toggle, so they're only sent when this says they're needed.
+    fn in_scratchpad(&self, window_id: WindowId) -> Result<bool> {
+        let tree = self.ipc.get_tree()?;
+        Ok(workspace_of(&tree, window_id.0, None).as_deref() == Some(SCRATCHPAD_WORKSPACE))
     }
 }
 
 impl WindowManager for SwayManager {
+    fn backend(&self) -> Backend {
+        Backend::Sway
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            move_windows: true,
+            resize: true,
+            minimize: true,
+            events: true,
+            thumbnails: false,
+        }
+    }
+
     fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
         let windows = self.get_all_windows()?;
         let mut eve_windows = Vec::new();
@@ -292,50 +512,38 @@ impl WindowManager for SwayManager {
         Ok(eve_windows)
     }
 
-    fn activate_window(&self, window_id: u32) -> Result<()> {
-        let output = Command::new("swaymsg")
-            .arg(format!("[con_id={}] focus", window_id))
-            .output()
-            .context("Failed to activate window")?;
-
-        if !output.status.success() {
-            anyhow::bail!(
-                "Failed to activate window: {}",
-                String::from_utf8_lossy(&output.stderr)
-            );
-        }
-
-        Ok(())
+    fn activate_window(&self, window_id: WindowId) -> Result<()> {
+        self.ipc
+            .run_command(&format!("[con_id={}] focus", window_id))
+            .context("Failed to activate window")
     }
 
     fn stack_windows(&self, windows: &[EveWindow], config: &Config) -> Result<()> {
+        if windows.is_empty() {
+            return Ok(());
+        }
+
         let x = ((config.display_width - config.eve_width) / 2) as i32;
         let y = 0;
         let width = config.eve_width as i32;
         let height = (config.display_height - config.panel_height) as i32;
 
-        for window in windows {
-            // Sway uses floating mode for positioning
-            Command::new("swaymsg")
-                .arg(format!("[con_id={}] floating enable", window.id))
-                .output()?;
-
-            Command::new("swaymsg")
-                .arg(format!("[con_id={}] move position {} {}", window.id, x, y))
-                .output()?;
-
-            Command::new("swaymsg")
-                .arg(format!(
-                    "[con_id={}] resize set {} {}",
-                    window.id, width, height
-                ))
-                .output()?;
-        }
-
-        Ok(())
+        // Sway uses floating mode for positioning; send every window's
+        // commands in one message
+        let commands: Vec<String> = windows
+            .iter()
+            .map(|window| {
+                format!(
+                    "[con_id={}] floating enable, move position {} {}, resize set {} {}",
+                    window.id, x, y, width, height
+                )
+            })
+            .collect();
+
+        self.ipc.run_command(&commands.join("; "))
     }
 
-    fn get_active_window(&self) -> Result<u32> {
+    fn get_active_window(&self) -> Result<WindowId> {
         let windows = self.get_all_windows()?;
 
         for window in windows {
@@ -351,7 +559,7 @@ impl WindowManager for SwayManager {
         anyhow::bail!("No active window found")
     }
 
-    fn find_window_by_title(&self, title: &str) -> Result<Option<u32>> {
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowId>> {
         let windows = self.get_all_windows()?;
 
         for window in windows {
@@ -367,63 +575,174 @@ impl WindowManager for SwayManager {
         Ok(None)
     }
 
-    fn minimize_window(&self, window_id: u32) -> Result<()> {
-        Command::new("swaymsg")
-            .arg(format!("[con_id={}] move scratchpad", window_id))
-            .output()
-            .context("Failed to minimize window")?;
-        Ok(())
+    fn minimize_window(&self, window_id: WindowId) -> Result<()> {
+        // `move scratchpad` is already set-style, so unlike restore this needs no tree
+        self.ipc
+            .run_command(&format!("[con_id={}] move scratchpad", window_id))
+            .context("Failed to minimize window")
     }
 
-    fn restore_window(&self, window_id: u32) -> Result<()> {
-        // Show from scratchpad restores it
-        Command::new("swaymsg")
-            .arg(format!("[con_id={}] scratchpad show", window_id))
-            .output()
-            .context("Failed to restore window")?;
-        Ok(())
+    fn move_window(&self, window_id: WindowId, x: i32, y: i32) -> Result<()> {
+        // Only takes effect on floating containers
+        self.ipc
+            .run_command(&format!("[con_id={}] move position {} {}", window_id, x, y))
+            .context("Failed to move window")
+    }
+
+    fn restore_window(&self, window_id: WindowId) -> Result<()> {
+        // Showing a visible scratchpad window would hide it instead
+        if !self.in_scratchpad(window_id)? {
+            return Ok(());
+        }
+        self.ipc
+            .run_command(&format!("[con_id={}] scratchpad show", window_id))
+            .context("Failed to restore window")
+    }
+
+    fn watch_windows(&self, notify: Sender<()>) -> Result<bool> {
+        let events = self.ipc.subscribe(&["window"])?;
+        std::thread::spawn(move || {
+            // Every window event (new, close, focus, title, ...) may matter
+            for event in events {
+                if event.is_err() || notify.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(true)
     }
 }
 
 // ============================================================================
-// Hyprland Backend (via hyprctl)
+// Hyprland Backend (via Hyprland's request and event sockets)
 // ============================================================================
 
-pub struct HyprlandManager;
+pub struct HyprlandManager {
+    /// `$XDG_RUNTIME_DIR/hypr/<instance signature>`, holding `.socket.sock`
+    /// (requests) and `.socket2.sock` (events)
+    socket_dir: PathBuf,
+}
+
+/// Where Hyprland puts its sockets: under `$XDG_RUNTIME_DIR/hypr` on current
+/// releases, `/tmp/hypr` on older ones
+fn hyprland_socket_dirs(xdg_runtime_dir: Option<OsString>, signature: &str) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(runtime_dir) = xdg_runtime_dir {
+        dirs.push(PathBuf::from(runtime_dir).join("hypr").join(signature));
+    }
+    dirs.push(PathBuf::from("/tmp/hypr").join(signature));
+    dirs
+}
+
+/// Check a dispatch reply: "ok" for each dispatch in the request
+fn check_dispatch_reply(reply: &str) -> Result<()> {
+    if reply.split_whitespace().all(|part| part == "ok") {
+        Ok(())
+    } else {
+        anyhow::bail!("Hyprland dispatch failed: {}", reply.trim())
+    }
+}
+
+/// Whether a `.socket2.sock` event line (`EVENT>>DATA`) can change the EVE
+/// window list or the active window
+fn is_window_event(line: &str) -> bool {
+    let event = line.split(">>").next().unwrap_or("");
+    matches!(
+        event,
+        "activewindowv2" | "openwindow" | "closewindow" | "windowtitle" | "windowtitlev2"
+    )
+}
 
 impl HyprlandManager {
     pub fn new() -> Result<Self> {
-        // Verify hyprctl is available
-        Command::new("hyprctl")
-            .arg("version")
-            .output()
-            .context("hyprctl not found. Make sure you're running Hyprland")?;
+        let signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE")
+            .context("HYPRLAND_INSTANCE_SIGNATURE not set. Make sure you're running Hyprland")?;
+
+        let socket_dir = hyprland_socket_dirs(std::env::var_os("XDG_RUNTIME_DIR"), &signature)
+            .into_iter()
+            .find(|dir| dir.join(".socket.sock").exists())
+            .context("Hyprland socket not found")?;
 
-        Ok(Self)
+        Ok(Self::with_socket_dir(socket_dir))
     }
 
-    fn get_all_windows(&self) -> Result<Vec<Value>> {
-        let output = Command::new("hyprctl")
-            .arg("clients")
-            .arg("-j")
-            .output()
-            .context("Failed to execute hyprctl")?;
-
-        if !output.status.success() {
-            anyhow::bail!(
-                "hyprctl failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            );
+    pub fn with_socket_dir(socket_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            socket_dir: socket_dir.into(),
         }
+    }
 
-        let windows: Vec<Value> =
-            serde_json::from_slice(&output.stdout).context("Failed to parse hyprctl output")?;
+    /// Send one request; Hyprland answers and closes the connection
+    fn request(&self, request: &str) -> Result<String> {
+        let path = self.socket_dir.join(".socket.sock");
+        let mut stream = UnixStream::connect(&path)
+            .with_context(|| format!("Failed to connect to {}", path.display()))?;
+        stream.write_all(request.as_bytes())?;
+
+        let mut reply = String::new();
+        stream
+            .read_to_string(&mut reply)
+            .context("Failed to read Hyprland reply")?;
+        Ok(reply)
+    }
 
-        Ok(windows)
+    fn request_json<T: serde::de::DeserializeOwned>(&self, request: &str) -> Result<T> {
+        let reply = self.request(&format!("j/{}", request))?;
+        serde_json::from_str(&reply).with_context(|| format!("Failed to parse {} reply", request))
+    }
+
+    /// Run dispatchers in a single request (as a `[[BATCH]]` if more than one)
+    fn dispatch(&self, dispatches: &[String]) -> Result<()> {
+        let request = match dispatches {
+            [] => return Ok(()),
+            [single] => format!("dispatch {}", single),
+            many => {
+                let commands: Vec<String> =
+                    many.iter().map(|d| format!("dispatch {}", d)).collect();
+                format!("[[BATCH]]{}", commands.join(" ; "))
+            }
+        };
+        check_dispatch_reply(&self.request(&request)?)
+    }
+
+    fn get_all_windows(&self) -> Result<Vec<Value>> {
+        self.request_json("clients")
+    }
+
+    /// Whether the window is parked on a special workspace (our minimized state)
+    fn is_hidden(&self, window_id: WindowId) -> Result<bool> {
+        let window = self
+            .get_all_windows()?
+            .into_iter()
+            .find(|w| {
+                w.get("address")
+                    .and_then(|a| a.as_str())
+                    .and_then(WindowId::from_hex)
+                    == Some(window_id)
+            })
+            .with_context(|| format!("Window 0x{:x} not found", window_id))?;
+
+        Ok(window["workspace"]["name"]
+            .as_str()
+            .is_some_and(|name| name.starts_with("special")))
     }
 }
 
 impl WindowManager for HyprlandManager {
+    fn backend(&self) -> Backend {
+        Backend::Hyprland
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            move_windows: false,
+            resize: true,
+            minimize: true,
+            events: true,
+            thumbnails: false,
+        }
+    }
+
     fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
         let windows = self.get_all_windows()?;
         let mut eve_windows = Vec::new();
@@ -431,15 +750,11 @@ impl WindowManager for HyprlandManager {
         for window in windows {
             if let Some(title) = window.get("title").and_then(|t| t.as_str()) {
                 if title.starts_with("EVE - ") && !title.contains("Launcher") {
-                    // Hyprland uses hex addresses, we'll hash it to a u32
-                    if let Some(address) = window.get("address").and_then(|a| a.as_str()) {
-                        // Convert hex address like "0x12345678" to u32
-                        let id = if let Some(hex) = address.strip_prefix("0x") {
-                            u32::from_str_radix(hex, 16).unwrap_or(0)
-                        } else {
-                            0
-                        };
-
+                    if let Some(id) = window
+                        .get("address")
+                        .and_then(|a| a.as_str())
+                        .and_then(WindowId::from_hex)
+                    {
                         eve_windows.push(EveWindow {
                             id,
                             title: title.trim_start_matches("EVE - ").to_string(),
@@ -452,25 +767,9 @@ impl WindowManager for HyprlandManager {
         Ok(eve_windows)
     }
 
-    fn activate_window(&self, window_id: u32) -> Result<()> {
-        // Convert u32 back to hex address
-        let address = format!("0x{:x}", window_id);
-
-        let output = Command::new("hyprctl")
-            .arg("dispatch")
-            .arg("focuswindow")
-            .arg(format!("address:{}", address))
-            .output()
-            .context("Failed to activate window")?;
-
-        if !output.status.success() {
-            anyhow::bail!(
-                "Failed to activate window: {}",
-                String::from_utf8_lossy(&output.stderr)
-            );
-        }
-
-        Ok(())
+    fn activate_window(&self, window_id: WindowId) -> Result<()> {
+        self.dispatch(&[format!("focuswindow address:0x{:x}", window_id)])
+            .context("Failed to activate window")
     }
 
     fn stack_windows(&self, windows: &[EveWindow], config: &Config) -> Result<()> {
@@ -479,68 +778,48 @@ impl WindowManager for HyprlandManager {
         let width = config.eve_width as i32;
         let height = (config.display_height - config.panel_height) as i32;
 
+        let mut dispatches = Vec::new();
         for window in windows {
             let address = format!("0x{:x}", window.id);
-
-            // Enable floating
-            Command::new("hyprctl")
-                .arg("dispatch")
-                .arg("togglefloating")
-                .arg(format!("address:{}", address))
-                .output()?;
-
-            // Move window
-            Command::new("hyprctl")
-                .arg("dispatch")
-                .arg("movewindowpixel")
-                .arg(format!("exact {} {},address:{}", x, y, address))
-                .output()?;
-
-            // Resize window
-            Command::new("hyprctl")
-                .arg("dispatch")
-                .arg("resizewindowpixel")
-                .arg(format!("exact {} {},address:{}", width, height, address))
-                .output()?;
+            dispatches.push(format!("setfloating address:{}", address));
+            dispatches.push(format!(
+                "movewindowpixel exact {} {},address:{}",
+                x, y, address
+            ));
+            dispatches.push(format!(
+                "resizewindowpixel exact {} {},address:{}",
+                width, height, address
+            ));
         }
 
-        Ok(())
+        self.dispatch(&dispatches)
     }
 
-    fn get_active_window(&self) -> Result<u32> {
-        let output = Command::new("hyprctl")
-            .arg("activewindow")
-            .arg("-j")
-            .output()
-            .context("Failed to get active window")?;
-
-        let window: Value =
-            serde_json::from_slice(&output.stdout).context("Failed to parse hyprctl output")?;
+    fn get_active_window(&self) -> Result<WindowId> {
+        let window: Value = self.request_json("activewindow")?;
 
-        if let Some(address) = window.get("address").and_then(|a| a.as_str()) {
-            let id = if let Some(hex) = address.strip_prefix("0x") {
-                u32::from_str_radix(hex, 16).unwrap_or(0)
-            } else {
-                0
-            };
+        if let Some(id) = window
+            .get("address")
+            .and_then(|a| a.as_str())
+            .and_then(WindowId::from_hex)
+        {
             return Ok(id);
         }
 
         anyhow::bail!("Failed to get active window ID")
     }
 
-    fn find_window_by_title(&self, title: &str) -> Result<Option<u32>> {
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowId>> {
         let windows = self.get_all_windows()?;
 
         for window in windows {
             if let Some(window_title) = window.get("title").and_then(|t| t.as_str()) {
                 if window_title == title {
-                    if let Some(address) = window.get("address").and_then(|a| a.as_str()) {
-                        let id = if let Some(hex) = address.strip_prefix("0x") {
-                            u32::from_str_radix(hex, 16).unwrap_or(0)
-                        } else {
-                            0
-                        };
+                    if let Some(id) = window
+                        .get("address")
+                        .and_then(|a| a.as_str())
+                        .and_then(WindowId::from_hex)
+                    {
                         return Ok(Some(id));
                     }
                 }
@@ -550,30 +829,541 @@ impl WindowManager for HyprlandManager {
         Ok(None)
     }
 
-    fn minimize_window(&self, window_id: u32) -> Result<()> {
-        let address = format!("0x{:x}", window_id);
-        Command::new("hyprctl")
-            .args([
-                "dispatch",
-                "movetoworkspacesilent",
-                &format!("special,address:{}", address),
-            ])
-            .output()
-            .context("Failed to minimize window")?;
+    fn minimize_window(&self, window_id: WindowId) -> Result<()> {
+        // Moving a hidden window to the special workspace again is a no-op, so
+        // unlike restore this needs no client list
+        self.dispatch(&[format!(
+            "movetoworkspacesilent special,address:0x{:x}",
+            window_id
+        )])
+        .context("Failed to minimize window")
+    }
+
+    fn restore_window(&self, window_id: WindowId) -> Result<()> {
+        if !self.is_hidden(window_id)? {
+            return Ok(());
+        }
+
+        // Move back to the current workspace
+        let workspace: Value = self.request_json("activeworkspace")?;
+        let workspace_id = workspace["id"]
+            .as_i64()
+            .context("Failed to get active workspace")?;
+        self.dispatch(&[format!(
+            "movetoworkspacesilent {},address:0x{:x}",
+            workspace_id, window_id
+        )])
+        .context("Failed to restore window")
+    }
+
+    fn watch_windows(&self, notify: Sender<()>) -> Result<bool> {
+        let path = self.socket_dir.join(".socket2.sock");
+        let stream = UnixStream::connect(&path)
+            .with_context(|| format!("Failed to connect to {}", path.display()))?;
+
+        std::thread::spawn(move || {
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else { break };
+                if is_window_event(&line) && notify.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(true)
+    }
+}
+
+// ============================================================================
+// niri Backend (via niri's JSON IPC socket)
+// ============================================================================
+
+pub struct NiriManager {
+    socket: PathBuf,
+}
+
+/// A window as listed by the `Windows` request
+#[derive(Debug, Deserialize)]
+struct NiriWindow {
+    id: u64,
+    title: Option<String>,
+}
+
+/// Unwrap a reply line: `{"Ok": ...}` or `{"Err": "message"}`
+fn niri_reply(line: &str) -> Result<Value> {
+    let reply: Value = serde_json::from_str(line).context("Failed to parse niri reply")?;
+    if let Some(ok) = reply.get("Ok") {
+        return Ok(ok.clone());
+    }
+    match reply.get("Err").and_then(|e| e.as_str()) {
+        Some(error) => anyhow::bail!("niri request failed: {}", error),
+        None => anyhow::bail!("Unexpected niri reply: {}", line.trim()),
+    }
+}
+
+/// Whether an event stream line can change the EVE window list or the active window
+fn is_niri_window_event(line: &str) -> bool {
+    let Ok(Value::Object(event)) = serde_json::from_str::<Value>(line) else {
+        return false;
+    };
+    event.keys().any(|kind| {
+        matches!(
+            kind.as_str(),
+            "WindowsChanged" | "WindowOpenedOrChanged" | "WindowClosed" | "WindowFocusChanged"
+        )
+    })
+}
+
+impl NiriManager {
+    pub fn new() -> Result<Self> {
+        let socket = std::env::var_os("NIRI_SOCKET")
+            .context("NIRI_SOCKET not set. Make sure you're running niri")?;
+        Ok(Self::with_socket(socket))
+    }
+
+    pub fn with_socket(path: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: path.into(),
+        }
+    }
+
+    /// Open a connection and send one request, returning the connection
+    /// positioned at the reply
+    fn connect(&self, request: &Value) -> Result<BufReader<UnixStream>> {
+        let mut stream = UnixStream::connect(&self.socket)
+            .with_context(|| format!("Failed to connect to {}", self.socket.display()))?;
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+        stream.write_all(&line)?;
+        Ok(BufReader::new(stream))
+    }
+
+    fn request(&self, request: &Value) -> Result<Value> {
+        let mut reader = self.connect(request)?;
+        let mut reply = String::new();
+        reader
+            .read_line(&mut reply)
+            .context("Failed to read niri reply")?;
+        niri_reply(&reply)
+    }
+
+    /// Run the actions in order, stopping at the first one niri rejects
+    fn actions(&self, actions: &[Value]) -> Result<()> {
+        for action in actions {
+            self.request(&serde_json::json!({ "Action": action }))?;
+        }
         Ok(())
     }
 
-    fn restore_window(&self, window_id: u32) -> Result<()> {
-        let address = format!("0x{:x}", window_id);
-        // Move back to current workspace
-        Command::new("hyprctl")
-            .args([
-                "dispatch",
-                "movetoworkspace",
-                &format!("e+0,address:{}", address),
-            ])
-            .output()
-            .context("Failed to restore window")?;
+    fn get_all_windows(&self) -> Result<Vec<NiriWindow>> {
+        let reply = self.request(&Value::from("Windows"))?;
+        serde_json::from_value(reply["Windows"].clone()).context("Failed to parse niri windows")
+    }
+}
+
+impl WindowManager for NiriManager {
+    fn backend(&self) -> Backend {
+        Backend::Niri
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            move_windows: false,
+            resize: true,
+            minimize: false,
+            events: true,
+            thumbnails: false,
+        }
+    }
+
+    fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
+        Ok(self
+            .get_all_windows()?
+            .into_iter()
+            .filter_map(|window| {
+                let title = window.title?;
+                (title.starts_with("EVE - ") && !title.contains("Launcher")).then(|| EveWindow {
+                    id: WindowId(window.id),
+                    title: title.trim_start_matches("EVE - ").to_string(),
+                })
+            })
+            .collect())
+    }
+
+    fn activate_window(&self, window_id: WindowId) -> Result<()> {
+        self.actions(&[serde_json::json!({ "FocusWindow": { "id": window_id.0 } })])
+            .context("Failed to activate window")
+    }
+
+    fn stack_windows(&self, windows: &[EveWindow], config: &Config) -> Result<()> {
+        let x = ((config.display_width - config.eve_width) / 2) as i32;
+        let y = 0;
+        let width = config.eve_width as i32;
+        let height = (config.display_height - config.panel_height) as i32;
+
+        // niri tiles into scrolling columns; only floating windows can be placed
+        let mut actions = Vec::new();
+        for window in windows {
+            let id = window.id.0;
+            actions.push(serde_json::json!({ "MoveWindowToFloating": { "id": id } }));
+            actions.push(serde_json::json!({
+                "SetWindowWidth": { "id": id, "change": { "SetFixed": width } }
+            }));
+            actions.push(serde_json::json!({
+                "SetWindowHeight": { "id": id, "change": { "SetFixed": height } }
+            }));
+            actions.push(serde_json::json!({
+                "MoveFloatingWindow": {
+                    "id": id,
+                    "x": { "SetFixed": x as f64 },
+                    "y": { "SetFixed": y as f64 }
+                }
+            }));
+        }
+
+        self.actions(&actions)
+    }
+
+    fn get_active_window(&self) -> Result<WindowId> {
+        let reply = self.request(&Value::from("FocusedWindow"))?;
+
+        match reply["FocusedWindow"].get("id").and_then(|i| i.as_u64()) {
+            Some(id) => Ok(WindowId(id)),
+            None => anyhow::bail!("No active window found"),
+        }
+    }
+
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowId>> {
+        Ok(self
+            .get_all_windows()?
+            .into_iter()
+            .find(|window| window.title.as_deref() == Some(title))
+            .map(|window| WindowId(window.id)))
+    }
+
+    fn minimize_window(&self, _window_id: WindowId) -> Result<()> {
+        anyhow::bail!("niri has no minimized window state")
+    }
+
+    fn restore_window(&self, _window_id: WindowId) -> Result<()> {
+        // Nothing was minimized; focusing the window is enough to bring it back
         Ok(())
     }
+
+    fn watch_windows(&self, notify: Sender<()>) -> Result<bool> {
+        let mut reader = self.connect(&Value::from("EventStream"))?;
+        let mut reply = String::new();
+        reader.read_line(&mut reply)?;
+        niri_reply(&reply)?;
+
+        std::thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else { break };
+                if is_niri_window_event(&line) && notify.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{serve, serve_socket, test_config, Connections};
+    use std::sync::mpsc::Receiver;
+
+    #[test]
+    fn test_sway_stack_sends_one_message() {
+        let (path, requests) = serve(
+            "sway-stack",
+            vec![(0, "[{\"success\":true},{\"success\":true}]".into())],
+        );
+        let sway = SwayManager::with_socket(&path).unwrap();
+        let windows = vec![
+            EveWindow {
+                id: WindowId(11),
+                title: "Alpha".to_string(),
+            },
+            EveWindow {
+                id: WindowId(12),
+                title: "Beta".to_string(),
+            },
+        ];
+
+        let mut config = test_config();
+        config.eve_width = 1000;
+        config.panel_height = 40;
+
+        sway.stack_windows(&windows, &config).unwrap();
+        assert_eq!(
+            requests.recv().unwrap(),
+            (
+                0,
+                "[con_id=11] floating enable, move position 460 0, resize set 1000 1040; \
+                 [con_id=12] floating enable, move position 460 0, resize set 1000 1040"
+                    .to_string()
+            )
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_hyprland_socket_dirs() {
+        assert_eq!(
+            hyprland_socket_dirs(Some("/run/user/1000".into()), "abc"),
+            vec![
+                PathBuf::from("/run/user/1000/hypr/abc"),
+                PathBuf::from("/tmp/hypr/abc"),
+            ]
+        );
+        assert_eq!(
+            hyprland_socket_dirs(None, "abc"),
+            vec![PathBuf::from("/tmp/hypr/abc")]
+        );
+    }
+
+    #[test]
+    fn test_kwin_window_ids_are_stable() {
+        let mut ids = KWinWindowIds::default();
+        let a = ids.id("{aaaa}");
+        let b = ids.id("{bbbb}");
+        assert_ne!(a, b);
+        assert_eq!(ids.id("{aaaa}"), a);
+        assert_eq!(ids.uuid(b).unwrap(), "{bbbb}");
+        assert!(ids.uuid(WindowId(99)).is_err());
+    }
+
+    #[test]
+    fn test_kwin_window_ids_forget_closed_windows() {
+        let mut ids = KWinWindowIds::default();
+        let a = ids.id("{aaaa}");
+        let b = ids.id("{bbbb}");
+
+        ids.retain(&[KWinWindow {
+            id: "{bbbb}".to_string(),
+            caption: String::new(),
+        }]);
+        assert!(ids.uuid(a).is_err());
+        assert_eq!(ids.id("{bbbb}"), b);
+        // A new window never takes over a closed one's id
+        let c = ids.id("{cccc}");
+        assert!(c != a && c != b);
+    }
+
+    #[test]
+    fn test_hyprland_dispatch_reply() {
+        assert!(check_dispatch_reply("ok").is_ok());
+        assert!(check_dispatch_reply("ok\n\nok\n\nok").is_ok());
+        assert!(check_dispatch_reply("ok\n\nNo such window found").is_err());
+    }
+
+    #[test]
+    fn test_hyprland_window_events() {
+        assert!(is_window_event("activewindowv2>>5581f2c4a0b0"));
+        assert!(is_window_event(
+            "openwindow>>5581f2c4a0b0,1,steam_app_8500,EVE"
+        ));
+        assert!(is_window_event("closewindow>>5581f2c4a0b0"));
+        assert!(is_window_event("windowtitle>>5581f2c4a0b0"));
+        assert!(!is_window_event("workspace>>2"));
+        assert!(!is_window_event("activewindow>>steam_app_8500,EVE - Alpha"));
+    }
+
+    /// Stand-in Hyprland socket directory: answers one request per connection
+    /// with the next canned reply and reports the requests it receives
+    fn serve_hyprland(name: &str, replies: Vec<&'static str>) -> (PathBuf, Receiver<String>) {
+        let dir = std::env::temp_dir().join(format!(
+            "nicotine-test-hypr-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+
+        let requests = serve_socket(
+            &dir.join(".socket.sock"),
+            replies,
+            Connections::PerRequest,
+            |stream, reply| {
+                let mut buffer = [0u8; 4096];
+                let n = stream.read(&mut buffer).unwrap();
+                stream.write_all(reply.as_bytes()).unwrap();
+                String::from_utf8(buffer[..n].to_vec()).unwrap()
+            },
+        );
+
+        (dir, requests)
+    }
+
+    #[test]
+    fn test_hyprland_stack_is_one_batch() {
+        let (dir, server) = serve_hyprland("stack", vec!["ok\n\nok\n\nok"]);
+        let hyprland = HyprlandManager::with_socket_dir(&dir);
+        let windows = vec![EveWindow {
+            id: WindowId(0x5581f2c4a0b0),
+            title: "Alpha".to_string(),
+        }];
+        hyprland.stack_windows(&windows, &test_config()).unwrap();
+
+        assert_eq!(
+            server.iter().collect::<Vec<_>>(),
+            vec![
+                "[[BATCH]]dispatch setfloating address:0x5581f2c4a0b0 ; \
+                 dispatch movewindowpixel exact 441 0,address:0x5581f2c4a0b0 ; \
+                 dispatch resizewindowpixel exact 1037 1080,address:0x5581f2c4a0b0"
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_hyprland_minimize_and_restore_are_idempotent() {
+        const HIDDEN: &str =
+            r#"[{"address":"0x5581f2c4a0b0","workspace":{"id":-98,"name":"special"}}]"#;
+        const VISIBLE: &str = r#"[{"address":"0x5581f2c4a0b0","workspace":{"id":2,"name":"2"}}]"#;
+        let (dir, server) = serve_hyprland(
+            "hidden",
+            vec!["ok", HIDDEN, r#"{"id":2,"name":"2"}"#, "ok", VISIBLE],
+        );
+        let hyprland = HyprlandManager::with_socket_dir(&dir);
+        let window = WindowId(0x5581f2c4a0b0);
+
+        // Set-style, so safe to repeat without checking first
+        hyprland.minimize_window(window).unwrap();
+        // Hidden: brought back to the active workspace without following it
+        hyprland.restore_window(window).unwrap();
+        // Already visible: nothing to do
+        hyprland.restore_window(window).unwrap();
+
+        assert_eq!(
+            server.iter().collect::<Vec<_>>(),
+            vec![
+                "dispatch movetoworkspacesilent special,address:0x5581f2c4a0b0",
+                "j/clients",
+                "j/activeworkspace",
+                "dispatch movetoworkspacesilent 2,address:0x5581f2c4a0b0",
+                "j/clients",
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_sway_restore_only_shows_hidden_windows() {
+        let tree = r#"{"id":1,"type":"root","nodes":[{"id":2,"type":"output","nodes":[
+            {"id":3,"type":"workspace","name":"__i3_scratch","nodes":[],"floating_nodes":[
+                {"id":11,"type":"floating_con","name":"EVE - Alpha","nodes":[]}]},
+            {"id":4,"type":"workspace","name":"1","nodes":[
+                {"id":12,"type":"con","name":"EVE - Beta","nodes":[]}]}]}]}"#;
+        let (path, requests) = serve(
+            "sway-restore",
+            vec![
+                (4, tree.into()),
+                (4, tree.into()),
+                (0, "[{\"success\":true}]".into()),
+                (0, "[{\"success\":true}]".into()),
+            ],
+        );
+        let sway = SwayManager::with_socket(&path).unwrap();
+
+        sway.restore_window(WindowId(12)).unwrap();
+        sway.restore_window(WindowId(11)).unwrap();
+        sway.minimize_window(WindowId(11)).unwrap();
+
+        let requests: Vec<(u32, String)> = requests.iter().collect();
+        assert_eq!(
+            requests,
+            vec![
+                (4, String::new()),
+                (4, String::new()),
+                (0, "[con_id=11] scratchpad show".to_string()),
+                (0, "[con_id=11] move scratchpad".to_string()),
+            ]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Stand-in niri socket: answers one request per connection with the next
+    /// canned reply and reports the requests it receives
+    fn serve_niri(name: &str, replies: Vec<&'static str>) -> (PathBuf, Receiver<String>) {
+        let path = std::env::temp_dir().join(format!(
+            "nicotine-test-niri-{}-{}.sock",
+            name,
+            std::process::id()
+        ));
+
+        let requests = serve_socket(&path, replies, Connections::PerRequest, |stream, reply| {
+            let mut request = String::new();
+            BufReader::new(&*stream).read_line(&mut request).unwrap();
+            writeln!(stream, "{}", reply).unwrap();
+            request.trim_end().to_string()
+        });
+
+        (path, requests)
+    }
+
+    #[test]
+    fn test_niri_reply() {
+        assert_eq!(niri_reply("{\"Ok\":\"Handled\"}").unwrap(), "Handled");
+        let err = niri_reply("{\"Err\":\"window not found\"}").unwrap_err();
+        assert!(err.to_string().contains("window not found"));
+        assert!(niri_reply("\"Handled\"").is_err());
+    }
+
+    #[test]
+    fn test_niri_window_events() {
+        assert!(is_niri_window_event("{\"WindowFocusChanged\":{\"id\":7}}"));
+        assert!(is_niri_window_event("{\"WindowClosed\":{\"id\":7}}"));
+        assert!(is_niri_window_event(
+            "{\"WindowOpenedOrChanged\":{\"window\":{\"id\":7,\"title\":\"EVE - Alpha\"}}}"
+        ));
+        assert!(!is_niri_window_event(
+            "{\"WorkspaceActivated\":{\"id\":2,\"focused\":true}}"
+        ));
+        assert!(!is_niri_window_event("not json"));
+    }
+
+    #[test]
+    fn test_niri_lists_eve_windows() {
+        let (path, server) = serve_niri(
+            "list",
+            vec![
+                r#"{"Ok":{"Windows":[{"id":7,"title":"EVE - Alpha","app_id":"steam_app_8500","is_focused":true},{"id":8,"title":null},{"id":9,"title":"EVE Launcher"}]}}"#,
+            ],
+        );
+        let niri = NiriManager::with_socket(&path);
+
+        assert_eq!(
+            niri.get_eve_windows().unwrap(),
+            vec![EveWindow {
+                id: WindowId(7),
+                title: "Alpha".to_string(),
+            }]
+        );
+        assert_eq!(server.iter().collect::<Vec<_>>(), vec!["\"Windows\""]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_niri_stack_floats_and_places_each_window() {
+        let (path, server) = serve_niri("stack", vec![r#"{"Ok":"Handled"}"#; 4]);
+        let niri = NiriManager::with_socket(&path);
+        let windows = vec![EveWindow {
+            id: WindowId(7),
+            title: "Alpha".to_string(),
+        }];
+
+        niri.stack_windows(&windows, &test_config()).unwrap();
+        assert_eq!(
+            server.iter().collect::<Vec<_>>(),
+            vec![
+                r#"{"Action":{"MoveWindowToFloating":{"id":7}}}"#,
+                r#"{"Action":{"SetWindowWidth":{"change":{"SetFixed":1037},"id":7}}}"#,
+                r#"{"Action":{"SetWindowHeight":{"change":{"SetFixed":1080},"id":7}}}"#,
+                r#"{"Action":{"MoveFloatingWindow":{"id":7,"x":{"SetFixed":441.0},"y":{"SetFixed":0.0}}}}"#,
+            ]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
 }
@@ -1,41 +1,174 @@
 use crate::config::Config;
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::mpsc::Sender;
 
-#[derive(Debug, Clone)]
+/// Backend-neutral window identifier: an X11 XID, a sway con_id or a Hyprland
+/// window address. All of them fit in 64 bits, so none are truncated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct WindowId(pub u64);
+
+impl WindowId {
+    /// Parse a hex ID as printed by wmctrl and Hyprland, e.g. "0x06e00008"
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let digits = hex.strip_prefix("0x")?;
+        u64::from_str_radix(digits, 16).ok().map(Self)
+    }
+
+    /// The X11 window this refers to. Only meaningful for IDs that came from
+    /// an X11 backend, where they're always 32-bit XIDs.
+    pub fn as_xid(self) -> u32 {
+        self.0 as u32
+    }
+}
+
+impl From<u32> for WindowId {
+    fn from(xid: u32) -> Self {
+        Self(u64::from(xid))
+    }
+}
+
+impl fmt::Display for WindowId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::LowerHex for WindowId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.0, f)
+    }
+}
+
+/// Which window manager implementation to use; `backend` in config.toml
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Pick one from the session (the default)
+    Auto,
+    X11,
+    I3,
+    Kde,
+    Gnome,
+    Sway,
+    Hyprland,
+    Niri,
+    /// Any compositor with wlr-foreign-toplevel-management
+    Wlr,
+}
+
+impl Backend {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Backend::Auto => "auto",
+            Backend::X11 => "x11",
+            Backend::I3 => "i3",
+            Backend::Kde => "kde",
+            Backend::Gnome => "gnome",
+            Backend::Sway => "sway",
+            Backend::Hyprland => "hyprland",
+            Backend::Niri => "niri",
+            Backend::Wlr => "wlr",
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What a backend can actually do, so callers can skip or hide features
+/// instead of having them silently no-op
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// `move_window` repositions windows
+    pub move_windows: bool,
+    /// `stack_windows` can place and resize EVE clients
+    pub resize: bool,
+    /// `minimize_window` and `restore_window` work
+    pub minimize: bool,
+    /// `watch_windows` pushes changes instead of needing polling
+    pub events: bool,
+    /// Window contents can be captured for previews
+    pub thumbnails: bool,
+}
+
+impl Capabilities {
+    /// Names of the supported capabilities, for display
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.move_windows, "move"),
+            (self.resize, "resize"),
+            (self.minimize, "minimize"),
+            (self.events, "events"),
+            (self.thumbnails, "thumbnails"),
+        ]
+        .into_iter()
+        .filter(|(supported, _)| *supported)
+        .map(|(_, name)| name)
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct EveWindow {
-    pub id: u32,
+    pub id: WindowId,
     pub title: String,
 }
 
 /// Trait for window management across different display servers and compositors
 pub trait WindowManager: Send + Sync {
+    /// Which backend this is
+    fn backend(&self) -> Backend;
+
+    /// What this backend supports
+    fn capabilities(&self) -> Capabilities;
+
     /// Get all EVE Online client windows
     fn get_eve_windows(&self) -> Result<Vec<EveWindow>>;
 
     /// Activate/focus a specific window by ID
-    fn activate_window(&self, window_id: u32) -> Result<()>;
+    fn activate_window(&self, window_id: WindowId) -> Result<()>;
 
     /// Stack all EVE windows at the same position (centered)
     fn stack_windows(&self, windows: &[EveWindow], config: &Config) -> Result<()>;
 
     /// Get the currently active window ID
-    fn get_active_window(&self) -> Result<u32>;
+    fn get_active_window(&self) -> Result<WindowId>;
 
     /// Find a window by its title (returns window ID if found)
-    fn find_window_by_title(&self, title: &str) -> Result<Option<u32>>;
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowId>>;
 
-    /// Move a window to a specific position (X11 only, no-op on Wayland)
-    fn move_window(&self, window_id: u32, x: i32, y: i32) -> Result<()> {
-        // Default implementation: no-op (Wayland doesn't allow arbitrary window positioning)
+    /// Move a window to a specific position (no-op unless `capabilities().move_windows`)
+    fn move_window(&self, window_id: WindowId, x: i32, y: i32) -> Result<()> {
+        // Default implementation: no-op (most Wayland compositors don't allow arbitrary positioning)
         let _ = (window_id, x, y);
         Ok(())
     }
 
+    /// Top-left corner of a window, for toolkits that can't report where the
+    /// overlay is (winit on Wayland); `None` if the backend can't tell either
+    fn window_position(&self, window_id: WindowId) -> Result<Option<(i32, i32)>> {
+        let _ = window_id;
+        Ok(None)
+    }
+
     /// Minimize a window
-    fn minimize_window(&self, window_id: u32) -> Result<()>;
+    fn minimize_window(&self, window_id: WindowId) -> Result<()>;
 
     /// Restore a minimized window
-    fn restore_window(&self, window_id: u32) -> Result<()>;
+    fn restore_window(&self, window_id: WindowId) -> Result<()>;
+
+    /// Send on `notify` whenever the EVE window list or active window changes.
+    /// Returns false if the backend has no change notifications and must be polled.
+    fn watch_windows(&self, notify: Sender<()>) -> Result<bool> {
+        let _ = notify;
+        Ok(false)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -50,6 +183,7 @@ pub enum WaylandCompositor {
     Sway,     // Sway (wlroots)
     Hyprland, // Hyprland
     Gnome,    // GNOME Shell
+    Niri,     // niri
     Other,    // Other/unknown compositor
 }
 
@@ -87,6 +221,9 @@ pub fn detect_wayland_compositor() -> WaylandCompositor {
         if desktop_lower.contains("hyprland") {
             return WaylandCompositor::Hyprland;
         }
+        if desktop_lower.contains("niri") {
+            return WaylandCompositor::Niri;
+        }
     }
 
     // Check for compositor-specific environment variables
@@ -98,5 +235,45 @@ pub fn detect_wayland_compositor() -> WaylandCompositor {
         return WaylandCompositor::Hyprland;
     }
 
+    if std::env::var("NIRI_SOCKET").is_ok() {
+        return WaylandCompositor::Niri;
+    }
+
     WaylandCompositor::Other
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capability_names() {
+        let capabilities = Capabilities {
+            resize: true,
+            events: true,
+            ..Capabilities::default()
+        };
+        assert_eq!(capabilities.names(), vec!["resize", "events"]);
+        assert!(Capabilities::default().names().is_empty());
+    }
+
+    #[test]
+    fn test_window_id_from_hex() {
+        assert_eq!(WindowId::from_hex("0x06e00008"), Some(WindowId(0x06e00008)));
+        // Hyprland addresses don't fit in 32 bits
+        assert_eq!(
+            WindowId::from_hex("0x5581f2c4a0b0"),
+            Some(WindowId(0x5581f2c4a0b0))
+        );
+        assert_eq!(WindowId::from_hex("06e00008"), None);
+        assert_eq!(WindowId::from_hex("0xzz"), None);
+    }
+
+    #[test]
+    fn test_window_id_formatting() {
+        let id = WindowId(0x5581f2c4a0b0);
+        assert_eq!(format!("0x{:x}", id), "0x5581f2c4a0b0");
+        assert_eq!(id.to_string(), "94016612114608");
+        assert_eq!(serde_json::to_string(&id).unwrap(), "94016612114608");
+    }
+}
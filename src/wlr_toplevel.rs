@@ -0,0 +1,327 @@
+use crate::config::Config;
+use crate::window_manager::{Backend, Capabilities, EveWindow, WindowId, WindowManager};
+use anyhow::{Context, Result};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_registry, wl_seat};
+use wayland_client::{event_created_child, Connection, Dispatch, QueueHandle};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+/// Generic Wayland backend for compositors implementing
+/// `zwlr_foreign_toplevel_manager_v1` (river, labwc, wayfire, ...)
+///
+/// The protocol can list, activate, minimize and unminimize windows, but not
+/// move or resize them, so stacking is left to the compositor.
+pub struct WlrToplevelManager {
+    conn: Connection,
+    seat: wl_seat::WlSeat,
+    toplevels: Arc<Mutex<Toplevels>>,
+}
+
+struct Toplevel {
+    id: WindowId,
+    handle: ZwlrForeignToplevelHandleV1,
+    /// Committed state, updated on each `done` event
+    title: String,
+    activated: bool,
+    minimized: bool,
+    /// Title and state sent since the last `done`
+    pending_title: Option<String>,
+    pending_state: Option<(bool, bool)>,
+    /// Set once the compositor has sent the initial state
+    ready: bool,
+}
+
+#[derive(Default)]
+struct Toplevels {
+    /// In the order the compositor announced them
+    windows: Vec<Toplevel>,
+    next_id: u64,
+    notify: Option<Sender<()>>,
+}
+
+impl Toplevels {
+    fn add(&mut self, handle: ZwlrForeignToplevelHandleV1) {
+        self.next_id += 1;
+        self.windows.push(Toplevel {
+            id: WindowId(self.next_id),
+            handle,
+            title: String::new(),
+            activated: false,
+            minimized: false,
+            pending_title: None,
+            pending_state: None,
+            ready: false,
+        });
+    }
+
+    fn get_mut(&mut self, handle: &ZwlrForeignToplevelHandleV1) -> Option<&mut Toplevel> {
+        self.windows.iter_mut().find(|t| t.handle == *handle)
+    }
+
+    fn handle(&self, id: WindowId) -> Result<ZwlrForeignToplevelHandleV1> {
+        self.windows
+            .iter()
+            .find(|t| t.id == id)
+            .map(|t| t.handle.clone())
+            .with_context(|| format!("Window {} no longer exists", id))
+    }
+
+    fn changed(&self) {
+        if let Some(notify) = &self.notify {
+            let _ = notify.send(());
+        }
+    }
+}
+
+/// Decode a `state` event's array of u32 `state` enum values into (activated, minimized)
+fn parse_states(raw: &[u8]) -> (bool, bool) {
+    let mut activated = false;
+    let mut minimized = false;
+    for chunk in raw.chunks_exact(4) {
+        let value = u32::from_ne_bytes(chunk.try_into().unwrap());
+        match zwlr_foreign_toplevel_handle_v1::State::try_from(value) {
+            Ok(zwlr_foreign_toplevel_handle_v1::State::Activated) => activated = true,
+            Ok(zwlr_foreign_toplevel_handle_v1::State::Minimized) => minimized = true,
+            _ => {}
+        }
+    }
+    (activated, minimized)
+}
+
+/// Owned by the dispatch thread; everything it learns goes into `toplevels`
+struct DispatchState {
+    toplevels: Arc<Mutex<Toplevels>>,
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for DispatchState {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for DispatchState {
+    fn event(
+        _: &mut Self,
+        _: &wl_seat::WlSeat,
+        _: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for DispatchState {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            state.toplevels.lock().unwrap().add(toplevel);
+        }
+    }
+
+    event_created_child!(DispatchState, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for DispatchState {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let mut toplevels = state.toplevels.lock().unwrap();
+
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => {
+                if let Some(toplevel) = toplevels.get_mut(handle) {
+                    toplevel.pending_title = Some(title);
+                }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::State { state } => {
+                if let Some(toplevel) = toplevels.get_mut(handle) {
+                    toplevel.pending_state = Some(parse_states(&state));
+                }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Done => {
+                if let Some(toplevel) = toplevels.get_mut(handle) {
+                    if let Some(title) = toplevel.pending_title.take() {
+                        toplevel.title = title;
+                    }
+                    if let Some((activated, minimized)) = toplevel.pending_state.take() {
+                        toplevel.activated = activated;
+                        toplevel.minimized = minimized;
+                    }
+                    toplevel.ready = true;
+                    toplevels.changed();
+                }
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::Closed => {
+                toplevels.windows.retain(|t| t.handle != *handle);
+                handle.destroy();
+                toplevels.changed();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl WlrToplevelManager {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::connect_to_env().context("Failed to connect to Wayland display")?;
+        let (globals, mut queue) = registry_queue_init::<DispatchState>(&conn)
+            .context("Failed to read Wayland globals")?;
+        let qh = queue.handle();
+
+        let _manager: ZwlrForeignToplevelManagerV1 = globals
+            .bind(&qh, 1..=3, ())
+            .context("Compositor doesn't support wlr-foreign-toplevel-management")?;
+        let seat: wl_seat::WlSeat = globals
+            .bind(&qh, 1..=1, ())
+            .context("Compositor has no seat")?;
+
+        let toplevels = Arc::new(Mutex::new(Toplevels::default()));
+        let mut state = DispatchState {
+            toplevels: Arc::clone(&toplevels),
+        };
+
+        // First roundtrip announces the toplevels, the second delivers their state
+        queue.roundtrip(&mut state)?;
+        queue.roundtrip(&mut state)?;
+
+        std::thread::spawn(move || loop {
+            if let Err(e) = queue.blocking_dispatch(&mut state) {
+                eprintln!("Wayland connection lost: {}", e);
+                break;
+            }
+        });
+
+        Ok(Self {
+            conn,
+            seat,
+            toplevels,
+        })
+    }
+
+    fn request(
+        &self,
+        window_id: WindowId,
+        send: impl FnOnce(&ZwlrForeignToplevelHandleV1),
+    ) -> Result<()> {
+        let handle = self.toplevels.lock().unwrap().handle(window_id)?;
+        send(&handle);
+        self.conn.flush().context("Failed to send Wayland request")
+    }
+}
+
+impl WindowManager for WlrToplevelManager {
+    fn backend(&self) -> Backend {
+        Backend::Wlr
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            move_windows: false,
+            resize: false,
+            minimize: true,
+            events: true,
+            thumbnails: false,
+        }
+    }
+
+    fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
+        let toplevels = self.toplevels.lock().unwrap();
+
+        Ok(toplevels
+            .windows
+            .iter()
+            .filter(|t| t.ready)
+            .filter(|t| t.title.starts_with("EVE - ") && !t.title.contains("Launcher"))
+            .map(|t| EveWindow {
+                id: t.id,
+                title: t.title.trim_start_matches("EVE - ").to_string(),
+            })
+            .collect())
+    }
+
+    fn activate_window(&self, window_id: WindowId) -> Result<()> {
+        self.request(window_id, |handle| handle.activate(&self.seat))
+    }
+
+    fn stack_windows(&self, _windows: &[EveWindow], _config: &Config) -> Result<()> {
+        anyhow::bail!(
+            "This compositor doesn't let clients move or resize windows; \
+             use a window rule to place EVE clients instead"
+        )
+    }
+
+    fn get_active_window(&self) -> Result<WindowId> {
+        let toplevels = self.toplevels.lock().unwrap();
+        toplevels
+            .windows
+            .iter()
+            .find(|t| t.activated)
+            .map(|t| t.id)
+            .context("No active window found")
+    }
+
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowId>> {
+        let toplevels = self.toplevels.lock().unwrap();
+        Ok(toplevels
+            .windows
+            .iter()
+            .find(|t| t.title == title)
+            .map(|t| t.id))
+    }
+
+    fn minimize_window(&self, window_id: WindowId) -> Result<()> {
+        self.request(window_id, |handle| handle.set_minimized())
+    }
+
+    fn restore_window(&self, window_id: WindowId) -> Result<()> {
+        self.request(window_id, |handle| handle.unset_minimized())
+    }
+
+    fn watch_windows(&self, notify: Sender<()>) -> Result<bool> {
+        self.toplevels.lock().unwrap().notify = Some(notify);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn states(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_ne_bytes()).collect()
+    }
+
+    #[test]
+    fn test_parse_states() {
+        assert_eq!(parse_states(&states(&[])), (false, false));
+        assert_eq!(parse_states(&states(&[0, 2])), (true, false));
+        assert_eq!(parse_states(&states(&[1])), (false, true));
+        // Unknown values from newer protocol versions are ignored
+        assert_eq!(parse_states(&states(&[2, 1, 42])), (true, true));
+    }
+}
@@ -1,15 +1,263 @@
 use crate::config::Config;
-use crate::window_manager::{EveWindow, WindowManager};
+use crate::window_manager::{Backend, Capabilities, EveWindow, WindowId, WindowManager};
 use anyhow::{Context, Result};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use x11rb::atom_manager;
 use x11rb::connection::Connection;
 use x11rb::protocol::xproto::*;
+use x11rb::protocol::Event;
 use x11rb::rust_connection::RustConnection;
 
+atom_manager! {
+    /// Every atom the manager uses, interned together once at startup
+    Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST,
+        _NET_WM_NAME,
+        UTF8_STRING,
+        WM_CHANGE_STATE,
+    }
+}
+
 pub struct X11Manager {
     conn: Arc<RustConnection>,
     screen_num: usize,
-    net_active_window_atom: Atom,
+    atoms: Atoms,
+    /// Filled in once `watch_windows` starts tracking PropertyNotify events
+    cache: Arc<Mutex<Option<WindowCache>>>,
+}
+
+/// Client list, titles and active window as last reported by the X server
+#[derive(Default)]
+struct WindowCache {
+    /// Every managed window, in `_NET_CLIENT_LIST` order
+    clients: Vec<Window>,
+    titles: HashMap<Window, String>,
+    active: Window,
+}
+
+impl WindowCache {
+    /// Replace the client list, returning the windows that weren't in it before
+    fn set_clients(&mut self, clients: Vec<Window>) -> Vec<Window> {
+        self.titles.retain(|window, _| clients.contains(window));
+        let added = clients
+            .iter()
+            .copied()
+            .filter(|window| !self.titles.contains_key(window))
+            .collect();
+        self.clients = clients;
+        added
+    }
+
+    fn eve_windows(&self) -> Vec<EveWindow> {
+        self.clients
+            .iter()
+            .filter_map(|&window| eve_window(window, self.titles.get(&window)?))
+            .collect()
+    }
+}
+
+/// Filter for EVE windows (steam_app_8500) and exclude launcher
+fn eve_window(id: Window, title: &str) -> Option<EveWindow> {
+    if title.starts_with("EVE - ") && !title.contains("Launcher") {
+        Some(EveWindow {
+            id: WindowId::from(id),
+            title: title.trim_start_matches("EVE - ").to_string(),
+        })
+    } else {
+        None
+    }
+}
+
+fn read_client_list(
+    conn: &RustConnection,
+    root: Window,
+    net_client_list: Atom,
+) -> Result<Vec<Window>> {
+    let reply = conn
+        .get_property(false, root, net_client_list, AtomEnum::WINDOW, 0, u32::MAX)?
+        .reply()?;
+
+    let windows = reply
+        .value32()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get window list"))?
+        .collect();
+    Ok(windows)
+}
+
+fn read_active_window(
+    conn: &RustConnection,
+    root: Window,
+    net_active_window: Atom,
+) -> Result<Window> {
+    let reply = conn
+        .get_property(false, root, net_active_window, AtomEnum::WINDOW, 0, 1)?
+        .reply()?;
+
+    let active: Vec<u32> = reply
+        .value32()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get active window"))?
+        .collect();
+
+    Ok(*active.first().unwrap_or(&0))
+}
+
+/// Fetch the titles of `windows` with every request in flight at once, so
+/// enumerating clients costs one round-trip however many there are
+fn read_window_titles(
+    conn: &RustConnection,
+    windows: &[Window],
+    atoms: &Atoms,
+) -> Result<Vec<String>> {
+    let mut cookies = Vec::with_capacity(windows.len());
+    for &window in windows {
+        let net_wm_name = conn.get_property(
+            false,
+            window,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            0,
+            1024,
+        )?;
+        let wm_name =
+            conn.get_property(false, window, AtomEnum::WM_NAME, AtomEnum::STRING, 0, 1024)?;
+        cookies.push((net_wm_name, wm_name));
+    }
+
+    // A window closing mid-request just comes back without a title
+    Ok(cookies
+        .into_iter()
+        .map(|(net_wm_name, wm_name)| decode_title(net_wm_name.reply().ok(), wm_name.reply().ok()))
+        .collect())
+}
+
+fn read_window_title(conn: &RustConnection, window: Window, atoms: &Atoms) -> Result<String> {
+    Ok(read_window_titles(conn, &[window], atoms)?
+        .pop()
+        .unwrap_or_default())
+}
+
+fn decode_title(
+    net_wm_name: Option<GetPropertyReply>,
+    wm_name: Option<GetPropertyReply>,
+) -> String {
+    // Try _NET_WM_NAME first (UTF-8)
+    if let Some(reply) = net_wm_name {
+        if !reply.value.is_empty() {
+            if let Ok(title) = String::from_utf8(reply.value) {
+                return title;
+            }
+        }
+    }
+
+    // Fall back to WM_NAME
+    if let Some(reply) = wm_name {
+        if !reply.value.is_empty() {
+            return String::from_utf8_lossy(&reply.value).to_string();
+        }
+    }
+
+    String::new()
+}
+
+fn select_property_changes(conn: &RustConnection, window: Window) -> Result<()> {
+    let values = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+    conn.change_window_attributes(window, &values)?;
+    Ok(())
+}
+
+/// Refresh the client list, watching and recording titles for any new windows
+fn load_clients(
+    conn: &RustConnection,
+    root: Window,
+    cache: &mut WindowCache,
+    atoms: &Atoms,
+) -> Result<()> {
+    let clients = read_client_list(conn, root, atoms._NET_CLIENT_LIST)?;
+    let added = cache.set_clients(clients);
+    for &window in &added {
+        select_property_changes(conn, window)?;
+    }
+    let titles = read_window_titles(conn, &added, atoms)?;
+    cache.titles.extend(added.into_iter().zip(titles));
+    Ok(())
+}
+
+/// Apply PropertyNotify events to the cache until the connection fails or
+/// nobody is listening any more
+fn track_windows(
+    conn: &RustConnection,
+    root: Window,
+    atoms: Atoms,
+    cache: &Mutex<Option<WindowCache>>,
+    notify: &Sender<()>,
+) -> Result<()> {
+    loop {
+        let Event::PropertyNotify(event) = conn.wait_for_event()? else {
+            continue;
+        };
+
+        let changed = {
+            let mut guard = cache.lock().unwrap();
+            let Some(cache) = guard.as_mut() else {
+                return Ok(());
+            };
+            let previous_windows = cache.eve_windows();
+            let previous_active = cache.active;
+
+            if event.window == root && event.atom == atoms._NET_CLIENT_LIST {
+                load_clients(conn, root, cache, &atoms)?;
+            } else if event.window == root && event.atom == atoms._NET_ACTIVE_WINDOW {
+                cache.active = read_active_window(conn, root, atoms._NET_ACTIVE_WINDOW)?;
+            } else if (event.atom == atoms._NET_WM_NAME
+                || event.atom == Atom::from(AtomEnum::WM_NAME))
+                && cache.titles.contains_key(&event.window)
+            {
+                // A client logging in changes its title from "EVE" to "EVE - <name>"
+                let title = read_window_title(conn, event.window, &atoms)?;
+                cache.titles.insert(event.window, title);
+            }
+
+            cache.eve_windows() != previous_windows || cache.active != previous_active
+        };
+
+        if changed && notify.send(()).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// The IPC socket of a running i3, from `$I3SOCK` or the `_I3_SOCKET_PATH`
+/// property i3 sets on the root window
+pub fn i3_socket_path() -> Option<PathBuf> {
+    if let Some(socket) = std::env::var_os("I3SOCK") {
+        return Some(PathBuf::from(socket));
+    }
+
+    let (conn, screen_num) = RustConnection::connect(None).ok()?;
+    let root = conn.setup().roots[screen_num].root;
+
+    // Only exists once i3 has created it
+    let atom = conn
+        .intern_atom(true, b"_I3_SOCKET_PATH")
+        .ok()?
+        .reply()
+        .ok()?
+        .atom;
+    if atom == x11rb::NONE {
+        return None;
+    }
+
+    let reply = conn
+        .get_property(false, root, atom, AtomEnum::ANY, 0, 1024)
+        .ok()?
+        .reply()
+        .ok()?;
+    let path = String::from_utf8(reply.value).ok()?;
+    (!path.is_empty()).then(|| PathBuf::from(path))
 }
 
 impl X11Manager {
@@ -19,93 +267,89 @@ impl X11Manager {
 
         let conn = Arc::new(conn);
 
-        // Pre-cache the _NET_ACTIVE_WINDOW atom (do roundtrip once at startup)
-        let net_active_window_atom = conn
-            .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
-            .reply()?
-            .atom;
+        // Intern every atom up front (all requests pipelined into one round-trip)
+        let atoms = Atoms::new(conn.as_ref())?
+            .reply()
+            .context("Failed to intern X11 atoms")?;
 
         Ok(Self {
             conn,
             screen_num,
-            net_active_window_atom,
+            atoms,
+            cache: Arc::new(Mutex::new(None)),
         })
     }
 
     pub fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
+        if let Some(cache) = self.cache.lock().unwrap().as_ref() {
+            return Ok(cache.eve_windows());
+        }
+
         let screen = &self.conn.setup().roots[self.screen_num];
         let root = screen.root;
 
-        // Get _NET_CLIENT_LIST atom
-        let net_client_list = self
-            .conn
-            .intern_atom(false, b"_NET_CLIENT_LIST")?
-            .reply()?
-            .atom;
-
         // Get list of all windows
-        let client_list_reply = self
-            .conn
-            .get_property(false, root, net_client_list, AtomEnum::WINDOW, 0, u32::MAX)?
-            .reply()?;
-
-        let windows: Vec<u32> = client_list_reply
-            .value32()
-            .ok_or_else(|| anyhow::anyhow!("Failed to get window list"))?
-            .collect();
+        let windows = read_client_list(&self.conn, root, self.atoms._NET_CLIENT_LIST)?;
+        let titles = read_window_titles(&self.conn, &windows, &self.atoms)?;
+
+        Ok(windows
+            .into_iter()
+            .zip(titles)
+            .filter_map(|(window, title)| eve_window(window, &title))
+            .collect())
+    }
 
-        let mut eve_windows = Vec::new();
-
-        for &window in &windows {
-            if let Ok(title) = self.get_window_title(window) {
-                // Filter for EVE windows (steam_app_8500) and exclude launcher
-                if title.starts_with("EVE - ") && !title.contains("Launcher") {
-                    eve_windows.push(EveWindow {
-                        id: window,
-                        title: title.trim_start_matches("EVE - ").to_string(),
-                    });
-                }
-            }
+    pub fn get_active_window(&self) -> Result<WindowId> {
+        if let Some(cache) = self.cache.lock().unwrap().as_ref() {
+            return Ok(WindowId::from(cache.active));
         }
 
-        Ok(eve_windows)
-    }
-
-    pub fn get_active_window(&self) -> Result<u32> {
         let screen = &self.conn.setup().roots[self.screen_num];
-        let root = screen.root;
-
-        let net_active_window = self
-            .conn
-            .intern_atom(false, b"_NET_ACTIVE_WINDOW")?
-            .reply()?
-            .atom;
-
-        let reply = self
-            .conn
-            .get_property(false, root, net_active_window, AtomEnum::WINDOW, 0, 1)?
-            .reply()?;
+        read_active_window(&self.conn, screen.root, self.atoms._NET_ACTIVE_WINDOW)
+            .map(WindowId::from)
+    }
 
-        let active: Vec<u32> = reply
-            .value32()
-            .ok_or_else(|| anyhow::anyhow!("Failed to get active window"))?
-            .collect();
+    /// Select PropertyNotify on the root and every client window, then keep the
+    /// cache current from a background thread instead of re-reading everything
+    /// on each poll
+    pub fn watch_windows(&self, notify: Sender<()>) -> Result<bool> {
+        let root = self.conn.setup().roots[self.screen_num].root;
+        let atoms = self.atoms;
+
+        // Select before the initial read so no change can slip in between
+        select_property_changes(&self.conn, root)?;
+
+        let mut cache = WindowCache::default();
+        load_clients(&self.conn, root, &mut cache, &atoms)?;
+        cache.active = read_active_window(&self.conn, root, atoms._NET_ACTIVE_WINDOW)?;
+        *self.cache.lock().unwrap() = Some(cache);
+
+        let conn = Arc::clone(&self.conn);
+        let cache = Arc::clone(&self.cache);
+        std::thread::spawn(move || {
+            if let Err(e) = track_windows(&conn, root, atoms, &cache, &notify) {
+                eprintln!("X11 window tracking stopped: {:#}", e);
+            }
+            // Fall back to querying the server directly
+            *cache.lock().unwrap() = None;
+        });
 
-        Ok(*active.first().unwrap_or(&0))
+        Ok(true)
     }
 
-    pub fn activate_window(&self, window_id: u32) -> Result<()> {
+    pub fn activate_window(&self, window_id: WindowId) -> Result<()> {
         let screen = &self.conn.setup().roots[self.screen_num];
         let root = screen.root;
 
-        let current_active = self.get_active_window().unwrap_or(0);
+        let window_id = window_id.as_xid();
+        let current_active = self.get_active_window().map_or(0, WindowId::as_xid);
 
         let event = ClientMessageEvent {
             response_type: CLIENT_MESSAGE_EVENT,
             format: 32,
             sequence: 0,
             window: window_id,
-            type_: self.net_active_window_atom,
+            type_: self.atoms._NET_ACTIVE_WINDOW,
             data: ClientMessageData::from([2, x11rb::CURRENT_TIME, current_active, 0, 0]),
         };
 
@@ -139,91 +383,36 @@ impl X11Manager {
                 .width(width)
                 .height(height);
 
-            self.conn.configure_window(window.id, &values)?;
+            self.conn.configure_window(window.id.as_xid(), &values)?;
         }
 
         self.conn.flush()?;
         Ok(())
     }
 
-    fn get_window_title(&self, window: u32) -> Result<String> {
-        // Try _NET_WM_NAME first (UTF-8)
-        let net_wm_name = self.conn.intern_atom(false, b"_NET_WM_NAME")?.reply()?.atom;
-
-        let utf8_string = self.conn.intern_atom(false, b"UTF8_STRING")?.reply()?.atom;
-
-        if let Ok(reply) = self
-            .conn
-            .get_property(false, window, net_wm_name, utf8_string, 0, 1024)?
-            .reply()
-        {
-            if !reply.value.is_empty() {
-                if let Ok(title) = String::from_utf8(reply.value.clone()) {
-                    return Ok(title);
-                }
-            }
-        }
-
-        // Fall back to WM_NAME
-        if let Ok(reply) = self
-            .conn
-            .get_property(false, window, AtomEnum::WM_NAME, AtomEnum::STRING, 0, 1024)?
-            .reply()
-        {
-            if !reply.value.is_empty() {
-                return Ok(String::from_utf8_lossy(&reply.value).to_string());
-            }
-        }
-
-        Ok(String::new())
-    }
-
-    pub fn find_window_by_title(&self, title: &str) -> Result<Option<u32>> {
+    pub fn find_window_by_title(&self, title: &str) -> Result<Option<WindowId>> {
         let screen = &self.conn.setup().roots[self.screen_num];
         let root = screen.root;
 
-        let net_client_list = self
-            .conn
-            .intern_atom(false, b"_NET_CLIENT_LIST")?
-            .reply()?
-            .atom;
-
-        let client_list_reply = self
-            .conn
-            .get_property(false, root, net_client_list, AtomEnum::WINDOW, 0, u32::MAX)?
-            .reply()?;
-
-        let windows: Vec<u32> = client_list_reply
-            .value32()
-            .ok_or_else(|| anyhow::anyhow!("Failed to get window list"))?
-            .collect();
-
-        for &window in &windows {
-            if let Ok(window_title) = self.get_window_title(window) {
-                if window_title == title {
-                    return Ok(Some(window));
-                }
-            }
-        }
+        let windows = read_client_list(&self.conn, root, self.atoms._NET_CLIENT_LIST)?;
+        let titles = read_window_titles(&self.conn, &windows, &self.atoms)?;
 
-        Ok(None)
+        Ok(windows
+            .into_iter()
+            .zip(titles)
+            .find(|(_, window_title)| window_title == title)
+            .map(|(window, _)| WindowId::from(window)))
     }
 
-    pub fn move_window(&self, window_id: u32, x: i32, y: i32) -> Result<()> {
+    pub fn move_window(&self, window_id: WindowId, x: i32, y: i32) -> Result<()> {
         let values = ConfigureWindowAux::new().x(x).y(y);
-        self.conn.configure_window(window_id, &values)?;
+        self.conn.configure_window(window_id.as_xid(), &values)?;
         self.conn.flush()?;
         Ok(())
     }
 
-    pub fn minimize_window(&self, window_id: u32) -> Result<()> {
+    pub fn minimize_window(&self, window_id: WindowId) -> Result<()> {
         // Use WM_CHANGE_STATE with IconicState to minimize
-        let wm_change_state = self
-            .conn
-            .intern_atom(false, b"WM_CHANGE_STATE")?
-            .reply()?
-            .atom;
-
         let screen = &self.conn.setup().roots[self.screen_num];
         let root = screen.root;
 
@@ -232,8 +421,8 @@ impl X11Manager {
             response_type: CLIENT_MESSAGE_EVENT,
             format: 32,
             sequence: 0,
-            window: window_id,
-            type_: wm_change_state,
+            window: window_id.as_xid(),
+            type_: self.atoms.WM_CHANGE_STATE,
             data: ClientMessageData::from([3u32, 0, 0, 0, 0]),
         };
 
@@ -248,20 +437,34 @@ impl X11Manager {
         Ok(())
     }
 
-    pub fn restore_window(&self, window_id: u32) -> Result<()> {
+    pub fn restore_window(&self, window_id: WindowId) -> Result<()> {
         // Map the window to restore it from minimized state
-        self.conn.map_window(window_id)?;
+        self.conn.map_window(window_id.as_xid())?;
         self.conn.flush()?;
         Ok(())
     }
 }
 
 impl WindowManager for X11Manager {
+    fn backend(&self) -> Backend {
+        Backend::X11
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            move_windows: true,
+            resize: true,
+            minimize: true,
+            events: true,
+            thumbnails: true,
+        }
+    }
+
     fn get_eve_windows(&self) -> Result<Vec<EveWindow>> {
         self.get_eve_windows()
     }
 
-    fn activate_window(&self, window_id: u32) -> Result<()> {
+    fn activate_window(&self, window_id: WindowId) -> Result<()> {
         self.activate_window(window_id)
     }
 
@@ -274,23 +477,93 @@ impl WindowManager for X11Manager {
         self.stack_windows_internal(windows, x, y, width, height)
     }
 
-    fn get_active_window(&self) -> Result<u32> {
+    fn get_active_window(&self) -> Result<WindowId> {
         self.get_active_window()
     }
 
-    fn find_window_by_title(&self, title: &str) -> Result<Option<u32>> {
+    fn find_window_by_title(&self, title: &str) -> Result<Option<WindowId>> {
         self.find_window_by_title(title)
     }
 
-    fn move_window(&self, window_id: u32, x: i32, y: i32) -> Result<()> {
+    fn move_window(&self, window_id: WindowId, x: i32, y: i32) -> Result<()> {
         self.move_window(window_id, x, y)
     }
 
-    fn minimize_window(&self, window_id: u32) -> Result<()> {
+    fn minimize_window(&self, window_id: WindowId) -> Result<()> {
         self.minimize_window(window_id)
     }
 
-    fn restore_window(&self, window_id: u32) -> Result<()> {
+    fn restore_window(&self, window_id: WindowId) -> Result<()> {
         self.restore_window(window_id)
     }
+
+    fn watch_windows(&self, notify: Sender<()>) -> Result<bool> {
+        self.watch_windows(notify)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eve_window_filter() {
+        assert_eq!(
+            eve_window(1, "EVE - Alt One"),
+            Some(EveWindow {
+                id: WindowId(1),
+                title: "Alt One".to_string(),
+            })
+        );
+        assert_eq!(eve_window(2, "EVE"), None);
+        assert_eq!(eve_window(3, "EVE - Launcher"), None);
+        assert_eq!(eve_window(4, "Firefox"), None);
+    }
+
+    fn property_reply(value: &[u8]) -> Option<GetPropertyReply> {
+        Some(GetPropertyReply {
+            format: 8,
+            sequence: 0,
+            length: 0,
+            type_: 0,
+            bytes_after: 0,
+            value_len: value.len() as u32,
+            value: value.to_vec(),
+        })
+    }
+
+    #[test]
+    fn test_decode_title_prefers_net_wm_name() {
+        assert_eq!(
+            decode_title(
+                property_reply("EVE - Ålt".as_bytes()),
+                property_reply(b"EVE - Alt")
+            ),
+            "EVE - Ålt"
+        );
+        assert_eq!(
+            decode_title(property_reply(b""), property_reply(b"EVE - Alt")),
+            "EVE - Alt"
+        );
+        assert_eq!(decode_title(None, None), "");
+    }
+
+    #[test]
+    fn test_cache_tracks_client_list_changes() {
+        let mut cache = WindowCache::default();
+        assert_eq!(cache.set_clients(vec![1, 2]), vec![1, 2]);
+        cache.titles.insert(1, "EVE - Alpha".to_string());
+        cache.titles.insert(2, "Terminal".to_string());
+
+        // Only the new window needs its title fetched; closed ones are forgotten
+        assert_eq!(cache.set_clients(vec![2, 3, 1]), vec![3]);
+        cache.titles.insert(3, "EVE".to_string());
+        assert_eq!(cache.set_clients(vec![3, 1]), Vec::<Window>::new());
+        assert!(!cache.titles.contains_key(&2));
+
+        // Logging in renames the client, which makes it an EVE window
+        cache.titles.insert(3, "EVE - Beta".to_string());
+        let titles: Vec<String> = cache.eve_windows().into_iter().map(|w| w.title).collect();
+        assert_eq!(titles, vec!["Beta", "Alpha"]);
+    }
 }